@@ -80,23 +80,32 @@ impl AtlasOpts {
             }
 
             let all_textures: Vec<_> = fields.iter().flat_map(process_field).collect();
-            let field_getters = all_textures.iter().enumerate().map(|(i, ident_path)| {
-                let mut ident = String::new();
-                let mut ident_path = ident_path.iter();
-                if let Some(id) = ident_path.next() {
-                    ident += &id.to_string();
-                }
-                for id in ident_path {
-                    ident.push('_');
-                    ident += &id.to_string();
-                }
-                let ident = syn::Ident::new(&ident, proc_macro2::Span::mixed_site());
+            let names: Vec<String> = all_textures
+                .iter()
+                .map(|ident_path| {
+                    let mut ident = String::new();
+                    let mut ident_path = ident_path.iter();
+                    if let Some(id) = ident_path.next() {
+                        ident += &id.to_string();
+                    }
+                    for id in ident_path {
+                        ident.push('_');
+                        ident += &id.to_string();
+                    }
+                    ident
+                })
+                .collect();
+            let field_getters = names.iter().enumerate().map(|(i, ident)| {
+                let ident = syn::Ident::new(ident, proc_macro2::Span::mixed_site());
                 quote! {
                     #vis fn #ident(&self) -> #subtexture {
                         self.0.get(#i)
                     }
                 }
             });
+            let name_indices = names.iter().enumerate().map(|(i, name)| {
+                quote! { #name => Some(self.0.get(#i)), }
+            });
 
             let load_textures = all_textures.iter().map(|ident_path| {
                 let path_mut = ident_path.iter().map(|ident| {
@@ -127,6 +136,14 @@ impl AtlasOpts {
                     }
 
                     #(#field_getters)*
+
+                    /// Look up a texture by its field name.
+                    #vis fn get(&self, name: &str) -> Option<#subtexture> {
+                        match name {
+                            #(#name_indices)*
+                            _ => None,
+                        }
+                    }
                 }
 
                 impl geng::asset::Load for #struct_name {
@@ -0,0 +1,75 @@
+//! Runs the game simulation without a window.
+//!
+//! ```sh
+//! cargo run --example simulate -- --runs 1000
+//! ```
+
+use geng::prelude::*;
+use trake::model::*;
+
+#[derive(clap::Parser)]
+struct Opts {
+    /// Path to the game config.
+    #[clap(long, default_value = "assets/config.toml")]
+    config: std::path::PathBuf,
+    /// Number of runs to simulate.
+    #[clap(long, default_value_t = 100)]
+    runs: u64,
+    /// Seed of the first run, each next run increments it.
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// Stop a run after this many days.
+    #[clap(long, default_value_t = 100)]
+    max_days: usize,
+    /// Play back a recorded replay instead of simulating runs.
+    #[clap(long)]
+    replay: Option<std::path::PathBuf>,
+    /// Simulate runs on the level instead of generated boards.
+    #[clap(long)]
+    level: Option<std::path::PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let opts: Opts = clap::Parser::parse();
+
+    let config = std::fs::read_to_string(&opts.config)
+        .with_context(|| format!("when reading {:?}", opts.config))?;
+    let config: Config = toml::from_str(&config).context("when parsing the config")?;
+    config.validate().context("when validating the config")?;
+
+    let level = opts
+        .level
+        .as_ref()
+        .map(|path| Level::load_file(path, &config))
+        .transpose()?;
+
+    let delta_time = FloatTime::new(1.0 / trake::FIXED_FPS as f32);
+
+    if let Some(path) = &opts.replay {
+        let replay = Replay::load(path)?;
+        let model = replay.simulate(config, delta_time);
+        println!(
+            "replay {:?}: score {}, quotas {}, days {}, money {}",
+            path, model.total_score, model.quotas_completed, model.days_survived, model.money
+        );
+        return Ok(());
+    }
+
+    for seed in opts.seed..opts.seed + opts.runs {
+        let mut model = Model::with_level(config.clone(), seed, level.clone());
+        while model.days_survived < opts.max_days {
+            match model.phase {
+                Phase::Setup => model.launch_train(),
+                Phase::Resolution => model.update(delta_time, PlayerInput::default()),
+                Phase::GameOver | Phase::LevelComplete => break,
+            }
+            model.events.clear();
+        }
+        println!(
+            "seed {}: score {}, quotas {}, days {}, money {}",
+            seed, model.total_score, model.quotas_completed, model.days_survived, model.money
+        );
+    }
+
+    Ok(())
+}
@@ -48,6 +48,30 @@ pub struct Assets {
     pub shaders: ShaderAssets,
     pub fonts: FontAssets,
     pub sounds: SoundAssets,
+    /// Sprites of the resources defined in the config.
+    #[load(load_with = "load_resource_sprites(&manager, &base_path)")]
+    pub resources: HashMap<crate::model::ResourceId, Rc<PixelTexture>>,
+}
+
+fn load_resource_sprites(
+    manager: &geng::asset::Manager,
+    base_path: &std::path::Path,
+) -> geng::asset::Future<HashMap<crate::model::ResourceId, Rc<PixelTexture>>> {
+    let manager = manager.clone();
+    let base_path = base_path.to_owned();
+    async move {
+        let config: crate::model::Config =
+            geng::asset::Load::load(&manager, &base_path.join("config.toml"), &()).await?;
+        let mut sprites = HashMap::new();
+        for (id, resource) in config.resources {
+            let path = base_path.join(&resource.sprite);
+            let texture: PixelTexture =
+                geng::asset::Load::load(&manager, &path, &default()).await?;
+            sprites.insert(id, Rc::new(texture));
+        }
+        Ok(sprites)
+    }
+    .boxed_local()
 }
 
 trake_derive::texture_atlas!(pub SpritesAtlas {
@@ -74,15 +98,11 @@ pub struct SpriteAssets {
     pub locomotive: Rc<PixelTexture>,
     pub rail_straight: Rc<PixelTexture>,
     pub rail_left: Rc<PixelTexture>,
+    pub rail_junction: Rc<PixelTexture>,
+    pub rail_cross: Rc<PixelTexture>,
     pub wall: Rc<PixelTexture>,
     pub depo: Rc<PixelTexture>,
 
-    pub coal: Rc<PixelTexture>,
-    pub coin: Rc<PixelTexture>,
-    pub diamond: Rc<PixelTexture>,
-    pub plus_cent: Rc<PixelTexture>,
-
-    pub ghost_fuel: Rc<PixelTexture>,
     pub speed: Rc<PixelTexture>,
     pub feather_hat: Rc<PixelTexture>,
     pub spiral: Rc<PixelTexture>,
@@ -118,7 +138,12 @@ pub struct SoundAssets {
 
 impl Assets {
     pub async fn load(manager: &geng::asset::Manager) -> anyhow::Result<Self> {
-        geng::asset::Load::load(manager, &run_dir().join("assets"), &()).await
+        let assets: Self = geng::asset::Load::load(manager, &run_dir().join("assets"), &()).await?;
+        assets
+            .config
+            .validate()
+            .context("when validating the config")?;
+        Ok(assets)
     }
 }
 
@@ -4,14 +4,20 @@ use super::*;
 pub enum GameAction {
     LaunchTrain,
     BuyShop(usize),
+    Restart,
+    Continue,
+    SelectRail(RailKind),
 }
 
 impl GameState {
     pub fn execute(&mut self, action: GameAction) {
         log::trace!("Executing {:?}", action);
         match action {
-            GameAction::LaunchTrain => self.model.launch_train(),
-            GameAction::BuyShop(i) => self.model.buy_shop(i),
+            GameAction::LaunchTrain => self.apply(PlayerAction::LaunchTrain),
+            GameAction::BuyShop(i) => self.apply(PlayerAction::BuyShop(i)),
+            GameAction::Restart => self.restart(),
+            GameAction::Continue => self.continue_run(),
+            GameAction::SelectRail(kind) => self.place_rail_kind = kind,
         }
     }
 }
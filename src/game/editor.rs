@@ -0,0 +1,183 @@
+use super::*;
+
+/// Where the editor saves levels when no level was given through the command line.
+const DEFAULT_LEVEL_PATH: &str = "assets/levels/custom.ron";
+
+/// Seed of the play-tested runs, so that editing does not reroll the shop.
+const EDITOR_SEED: u64 = 0;
+
+pub struct LevelEditor {
+    pub level: Level,
+    pub path: PathBuf,
+    pub brush: EditorBrush,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditorBrush {
+    Wall,
+    Resource(ResourceId),
+    Rail,
+    Depo,
+}
+
+impl LevelEditor {
+    pub fn status(&self) -> Vec<String> {
+        let brush = match &self.brush {
+            EditorBrush::Resource(resource) => resource.clone(),
+            brush => format!("{:?}", brush),
+        };
+        vec![
+            format!("Editor: {}", brush),
+            format!("Size: {}x{}", self.level.map_size.x, self.level.map_size.y),
+        ]
+    }
+}
+
+impl GameState {
+    /// Enter or leave the level editor.
+    pub fn toggle_editor(&mut self) {
+        if self.editor.take().is_some() {
+            log::info!("Leaving the level editor");
+            self.restart();
+            return;
+        }
+
+        log::info!("Entering the level editor");
+        let level = self
+            .level
+            .clone()
+            .unwrap_or_else(|| Level::empty(self.context.assets.config.map_size));
+        let path = self
+            .level_path
+            .clone()
+            .unwrap_or_else(|| DEFAULT_LEVEL_PATH.into());
+        self.playback = None;
+        self.editor = Some(LevelEditor {
+            level,
+            path,
+            brush: EditorBrush::Wall,
+        });
+        self.rebuild_level();
+    }
+
+    /// Restart the model on the edited level, stopping the play-test.
+    fn rebuild_level(&mut self) {
+        let Some(editor) = &self.editor else {
+            return;
+        };
+        let config = self.context.assets.config.clone();
+        self.model = Model::with_level(config, EDITOR_SEED, Some(editor.level.clone()));
+    }
+
+    pub fn handle_editor_key(&mut self, key: geng::Key, ctrl: bool) {
+        if let geng::Key::Escape = key {
+            // Stop the play-test
+            self.rebuild_level();
+            return;
+        }
+        let Phase::Setup = self.model.phase else {
+            return;
+        };
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+
+        if ctrl {
+            match key {
+                geng::Key::S => {
+                    match editor.level.save_file(&editor.path) {
+                        Ok(()) => log::info!("Saved the level to {:?}", editor.path),
+                        Err(err) => log::error!("Failed to save the level: {:?}", err),
+                    }
+                    return;
+                }
+                geng::Key::O => match Level::load_file(&editor.path, &self.context.assets.config) {
+                    Ok(level) => {
+                        log::info!("Loaded the level from {:?}", editor.path);
+                        editor.level = level;
+                    }
+                    Err(err) => {
+                        log::error!("Failed to load the level: {:?}", err);
+                        return;
+                    }
+                },
+                _ => return,
+            }
+            self.rebuild_level();
+            return;
+        }
+
+        let mut resources: Vec<ResourceId> = self.model.config.resources.keys().cloned().collect();
+        resources.sort();
+        let size = editor.level.map_size;
+        match key {
+            geng::Key::W => editor.brush = EditorBrush::Wall,
+            geng::Key::T => editor.brush = EditorBrush::Rail,
+            geng::Key::D => editor.brush = EditorBrush::Depo,
+            geng::Key::C => {
+                // Cycle through the resources
+                let next = match &editor.brush {
+                    EditorBrush::Resource(current) => resources
+                        .iter()
+                        .position(|res| res == current)
+                        .map_or(0, |i| i + 1),
+                    _ => 0,
+                };
+                if let Some(res) = resources.get(next % resources.len().max(1)) {
+                    editor.brush = EditorBrush::Resource(res.clone());
+                }
+                return;
+            }
+            geng::Key::ArrowRight => editor.level.resize(size + vec2(1, 0)),
+            geng::Key::ArrowLeft => editor.level.resize(size - vec2(1, 0)),
+            geng::Key::ArrowUp => editor.level.resize(size + vec2(0, 1)),
+            geng::Key::ArrowDown => editor.level.resize(size - vec2(0, 1)),
+            _ => return,
+        }
+        self.rebuild_level();
+    }
+
+    pub fn handle_editor_mouse(&mut self, button: geng::MouseButton) {
+        let position = self.cursor_grid_pos;
+        let orientation = RailOrientation {
+            kind: self.place_rail_kind,
+            rotation: self.place_rotation,
+        };
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        let depo_size = self.context.assets.config.depo_size;
+        if !matches!(button, geng::MouseButton::Right)
+            && editor.brush != EditorBrush::Depo
+            && editor.level.depo_cells(depo_size).contains(&position)
+        {
+            // Keep the depo clear
+            return;
+        }
+
+        match button {
+            geng::MouseButton::Right => editor.level.clear_cell(position),
+            _ => match &editor.brush {
+                EditorBrush::Wall => editor.level.set_wall(position),
+                EditorBrush::Resource(resource) => {
+                    editor.level.set_resource(position, resource.clone())
+                }
+                EditorBrush::Rail => editor.level.set_rail(position, orientation),
+                EditorBrush::Depo => {
+                    if !editor.level.is_inside(position) {
+                        return;
+                    }
+                    editor.level.clear_cell(position);
+                    editor.level.depo = LevelDepo {
+                        exit: position,
+                        rotation: self.place_rotation,
+                    };
+                    for cell in editor.level.depo_cells(depo_size) {
+                        editor.level.clear_cell(cell);
+                    }
+                }
+            },
+        }
+        self.rebuild_level();
+    }
+}
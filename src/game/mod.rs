@@ -1,7 +1,8 @@
 mod actions;
+mod editor;
 mod ui;
 
-use self::{actions::*, ui::GameUi};
+use self::{actions::*, editor::*, ui::GameUi};
 
 use crate::{
     model::*,
@@ -13,6 +14,8 @@ use crate::{
     ui::UiContext,
 };
 
+use std::path::PathBuf;
+
 use geng_utils::key::EventKey;
 
 #[derive(geng::asset::Load, Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +24,7 @@ pub struct Controls {
     pub turn_left: Vec<EventKey>,
     pub turn_right: Vec<EventKey>,
     pub launch: Vec<EventKey>,
+    pub toggle_switches: Vec<EventKey>,
 }
 
 pub struct GameState {
@@ -29,7 +33,25 @@ pub struct GameState {
     unit_quad: ugli::VertexBuffer<draw2d::TexturedVertex>,
 
     render: GameRender,
+    /// Seed requested through the command line, random for every run otherwise.
+    seed: Option<u64>,
+    /// Level requested through the command line, generated boards otherwise.
+    level: Option<Level>,
+    /// Path the level was loaded from.
+    level_path: Option<PathBuf>,
+    /// Level editor, active while `Some`.
+    editor: Option<LevelEditor>,
     model: Model,
+    /// Recording of the current run.
+    replay: Replay,
+    /// Where to save the recording, if requested.
+    record_path: Option<PathBuf>,
+    /// Whether the run was resumed from a save, its recording cannot reproduce it.
+    resumed: bool,
+    /// Replay being played back, player actions are ignored meanwhile.
+    playback: Option<ReplayPlayer>,
+    /// Run saved in a previous session that can be continued.
+    saved_run: Option<RunSave>,
     ui: GameUi,
     ui_focused: bool,
 
@@ -48,11 +70,46 @@ pub struct GameState {
 }
 
 impl GameState {
-    pub fn new(context: Context) -> Self {
+    pub fn new(
+        context: Context,
+        seed: Option<u64>,
+        record_path: Option<PathBuf>,
+        playback: Option<Replay>,
+        level: Option<Level>,
+        level_path: Option<PathBuf>,
+    ) -> Self {
         context.music.play(&context.assets.sounds.tootuh);
+        let config = context.assets.config.clone();
+        let level = match &playback {
+            Some(replay) => replay.level.clone(),
+            None => level,
+        };
+        let run_seed = match &playback {
+            Some(replay) => {
+                if replay.config_hash != config_hash(&config) {
+                    log::warn!("Replay was recorded with a different config");
+                }
+                replay.seed
+            }
+            None => seed.unwrap_or_else(|| thread_rng().gen()),
+        };
+        let saved_run = if playback.is_some() {
+            None
+        } else {
+            preferences::load::<Option<RunSave>>(crate::RUN_STORAGE).flatten()
+        };
         Self {
             render: GameRender::new(context.clone()),
-            model: Model::new(context.clone(), context.assets.config.clone()),
+            seed,
+            saved_run,
+            replay: Replay::new(run_seed, &config, level.clone()),
+            record_path,
+            resumed: false,
+            playback: playback.map(ReplayPlayer::new),
+            model: Model::with_level(config, run_seed, level.clone()),
+            level,
+            level_path,
+            editor: None,
             ui: GameUi::new(),
             ui_focused: false,
 
@@ -75,7 +132,133 @@ impl GameState {
         }
     }
 
+    /// Start a fresh run.
+    fn restart(&mut self) {
+        if self.editor.is_some() {
+            self.rebuild_level();
+            return;
+        }
+        let seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+        let config = self.context.assets.config.clone();
+        self.replay = Replay::new(seed, &config, self.level.clone());
+        self.playback = None;
+        self.saved_run = None;
+        self.resumed = false;
+        self.model = Model::with_level(config, seed, self.level.clone());
+    }
+
+    /// Resume the run saved in a previous session.
+    fn continue_run(&mut self) {
+        let Some(save) = self.saved_run.take() else {
+            return;
+        };
+        if self.record_path.is_some() {
+            log::warn!("Resumed runs are not recorded");
+        }
+        let config = self.context.assets.config.clone();
+        self.replay = Replay::new(save.seed, &config, save.level.clone());
+        self.resumed = true;
+        self.model = Model::load(config, save);
+    }
+
+    fn save_run(&self) {
+        if self.playback.is_some() || self.editor.is_some() {
+            return;
+        }
+        if let Some(save) = self.model.save() {
+            preferences::save(crate::RUN_STORAGE, &Some(save));
+        }
+    }
+
+    /// Apply the action to the model and record it.
+    fn apply(&mut self, action: PlayerAction) {
+        if self.playback.is_some() {
+            log::debug!("Ignoring {:?} during replay playback", action);
+            return;
+        }
+        if self.editor.is_some() {
+            // Play-testing is not recorded
+            self.model.apply_action(action);
+            return;
+        }
+        self.saved_run = None;
+        self.replay.record_action(action.clone());
+        self.model.apply_action(action);
+        self.save_run();
+    }
+
+    fn save_replay(&self) {
+        if self.editor.is_some() || self.resumed {
+            return;
+        }
+        let Some(path) = &self.record_path else {
+            return;
+        };
+        if let Err(err) = self.replay.save(path) {
+            log::error!("Failed to save the replay: {:?}", err);
+        }
+    }
+
+    fn player_input(&mut self) -> PlayerInput {
+        let mut input = std::mem::take(&mut self.player_input);
+        let controls = &self.context.assets.controls;
+        let window = self.context.geng.window();
+        if geng_utils::key::is_key_pressed(window, &controls.turn_left) {
+            input.turn += r32(1.0);
+        } else if geng_utils::key::is_key_pressed(window, &controls.turn_right) {
+            input.turn -= r32(1.0);
+        }
+        input
+    }
+
+    fn handle_game_event(&mut self, event: GameEvent) {
+        let sounds = &self.context.assets.sounds;
+        match event {
+            GameEvent::TrainLaunched => self.context.play_sfx(&sounds.choochoo),
+            GameEvent::ResourceCollected { .. } => self.context.play_sfx(&sounds.clop2),
+            GameEvent::WagonDestroyed { .. } | GameEvent::Derailed { .. } => {
+                self.context.play_sfx(&sounds.puff)
+            }
+            GameEvent::RoundEnded { .. } => {
+                self.save_replay();
+                self.save_run();
+            }
+            GameEvent::GameOver { .. } | GameEvent::LevelCompleted { .. } => {
+                if self.editor.is_none() {
+                    self.save_replay();
+                    preferences::save(crate::RUN_STORAGE, &None::<RunSave>);
+                }
+            }
+            _ => {}
+        }
+        self.render.effects.handle_event(&self.model.config, &event);
+    }
+
     fn handle_key(&mut self, key: geng::Key) {
+        let window = self.context.geng.window();
+        let ctrl = window.is_key_pressed(geng::Key::ControlLeft)
+            || window.is_key_pressed(geng::Key::ControlRight);
+        let shift = window.is_key_pressed(geng::Key::ShiftLeft)
+            || window.is_key_pressed(geng::Key::ShiftRight);
+        if let geng::Key::F1 = key {
+            self.toggle_editor();
+            return;
+        }
+        if self.editor.is_some() {
+            self.handle_editor_key(key, ctrl);
+            if ctrl {
+                return;
+            }
+        } else if ctrl {
+            match key {
+                geng::Key::Z if shift => self.apply(PlayerAction::Redo),
+                geng::Key::Z => self.apply(PlayerAction::Undo),
+                geng::Key::Y => self.apply(PlayerAction::Redo),
+                _ => {}
+            }
+            return;
+        }
+
         match key {
             geng::Key::F2 => {
                 self.render_options.show_colliders = !self.render_options.show_colliders;
@@ -96,26 +279,57 @@ impl GameState {
             geng::Key::Digit2 => {
                 self.place_rail_kind = RailKind::Left;
             }
-            geng::Key::G => {
-                self.model.grid_items.insert(GridItem {
+            geng::Key::Digit3 => {
+                self.place_rail_kind = RailKind::Junction;
+            }
+            geng::Key::Digit4 => {
+                self.place_rail_kind = RailKind::Cross;
+            }
+            geng::Key::R if self.editor.is_none() => {
+                self.apply(PlayerAction::RotateRail(self.cursor_grid_pos));
+            }
+            geng::Key::G if self.editor.is_none() => {
+                self.apply(PlayerAction::SpawnResource {
                     position: self.cursor_grid_pos,
-                    rail: None,
-                    resource: Some(Resource::Coal),
-                    wall: None,
+                    resource: "Coal".into(),
                 });
             }
             _ => {}
         }
     }
 
-    fn handle_mouse(&mut self, _button: geng::MouseButton) {
-        self.model.place_rail(
-            self.cursor_grid_pos,
-            RailOrientation {
+    fn handle_mouse(&mut self, button: geng::MouseButton) {
+        if self.editor.is_some() {
+            if let Phase::Setup = self.model.phase {
+                self.handle_editor_mouse(button);
+            }
+            return;
+        }
+
+        if let geng::MouseButton::Right = button {
+            self.apply(PlayerAction::RemoveRail(self.cursor_grid_pos));
+            return;
+        }
+
+        match self.model.phase {
+            Phase::GameOver | Phase::LevelComplete => return,
+            Phase::Resolution => {
+                if let Some(rail) = self.model.rail_at(self.cursor_grid_pos) {
+                    if rail.orientation.kind == RailKind::Junction {
+                        self.apply(PlayerAction::ToggleSwitch(self.cursor_grid_pos));
+                        return;
+                    }
+                }
+            }
+            Phase::Setup => {}
+        }
+        self.apply(PlayerAction::PlaceRail {
+            position: self.cursor_grid_pos,
+            orientation: RailOrientation {
                 kind: self.place_rail_kind,
                 rotation: self.place_rotation,
             },
-        )
+        });
     }
 }
 
@@ -123,23 +337,49 @@ impl geng::State for GameState {
     fn update(&mut self, delta_time: f64) {
         let delta_time = r32(delta_time as f32);
         self.ui_context.update(delta_time.as_f32());
+        self.render.effects.update(&self.model, delta_time);
+    }
 
-        let mut input = std::mem::take(&mut self.player_input);
-        let controls = &self.context.assets.controls;
-        let window = self.context.geng.window();
-        if geng_utils::key::is_key_pressed(window, &controls.turn_left) {
-            input.turn += r32(1.0);
-        } else if geng_utils::key::is_key_pressed(window, &controls.turn_right) {
-            input.turn -= r32(1.0);
+    fn fixed_update(&mut self, delta_time: f64) {
+        let delta_time = r32(delta_time as f32);
+
+        let input = match &mut self.playback {
+            Some(player) => match player.next_tick(&mut self.model) {
+                Some(input) => input,
+                None => {
+                    // Playback is over, continue recording from it
+                    log::info!("Replay playback finished");
+                    if let Some(player) = self.playback.take() {
+                        self.replay = player.replay;
+                    }
+                    self.player_input()
+                }
+            },
+            None => self.player_input(),
+        };
+        if self.playback.is_none() && self.editor.is_none() {
+            self.replay.record_tick(&input);
         }
         self.model.update(delta_time, input);
+
+        self.context
+            .music
+            .set_volume(self.model.train.train_speed.as_f32().clamp(0.0, 1.0));
+        for event in std::mem::take(&mut self.model.events) {
+            self.handle_game_event(event);
+        }
     }
 
     fn handle_event(&mut self, event: geng::Event) {
         let controls = &self.context.assets.controls;
-        if geng_utils::key::is_event_press(&event, &controls.launch) {
+        let launch = geng_utils::key::is_event_press(&event, &controls.launch);
+        let toggle_switches = geng_utils::key::is_event_press(&event, &controls.toggle_switches);
+        if launch {
             self.execute(GameAction::LaunchTrain);
         }
+        if toggle_switches && self.editor.is_none() {
+            self.apply(PlayerAction::ToggleAllSwitches);
+        }
 
         match event {
             geng::Event::KeyPress { key } => self.handle_key(key),
@@ -169,6 +409,9 @@ impl geng::State for GameState {
 
         self.ui_context.state.frame_start();
         self.ui_context.geometry.update(framebuffer.size());
+        self.ui.can_continue = self.saved_run.is_some();
+        self.ui.selected_rail = self.place_rail_kind;
+        self.ui.editor_status = self.editor.as_ref().map(LevelEditor::status);
         let actions = self.ui.layout(
             &self.model,
             Aabb2::ZERO.extend_positive(framebuffer.size().as_f32()),
@@ -188,6 +431,19 @@ impl geng::State for GameState {
         ugli::clear(pixel_buffer, Some(bg_color), None, None);
         self.render
             .draw_game(&self.model, &self.render_options, pixel_buffer);
+        if !self.ui_focused && self.ui.game.hovered && self.editor.is_none() {
+            if let Phase::Setup | Phase::Resolution = self.model.phase {
+                self.render.draw_placement(
+                    &self.model,
+                    self.cursor_grid_pos,
+                    RailOrientation {
+                        kind: self.place_rail_kind,
+                        rotation: self.place_rotation,
+                    },
+                    pixel_buffer,
+                );
+            }
+        }
 
         let post_buffer = &mut self.post_buffer.active_draw();
         ugli::clear(post_buffer, Some(bg_color), None, None);
@@ -1,15 +1,27 @@
 use super::*;
 
-use crate::ui::{layout::AreaOps, widget::*};
+use crate::{
+    ui::{layout::AreaOps, widget::*},
+    util::SubTexture,
+};
 
 pub struct GameUi {
     pub game: WidgetState,
+    /// Whether there is a saved run to continue.
+    pub can_continue: bool,
+    /// Kind of rail placed on click.
+    pub selected_rail: RailKind,
+    /// Status lines of the level editor, if it is active.
+    pub editor_status: Option<Vec<String>>,
 }
 
 impl GameUi {
     pub fn new() -> Self {
         Self {
             game: WidgetState::new(),
+            can_continue: false,
+            selected_rail: RailKind::Straight,
+            editor_status: None,
         }
     }
 
@@ -39,10 +51,60 @@ impl GameUi {
 
         self.game.update(game, context);
 
+        // Game over
+        if model.phase.is_over() {
+            let mut summary = game
+                .with_width(font_size * 12.0, 0.5)
+                .with_height(font_size * 9.0, 0.5);
+
+            let pos = summary.cut_top(font_size * 2.0);
+            let title = context.state.get_root_or(|| TextWidget::new("Game Over"));
+            title.update(pos, context);
+            title.text = match model.phase {
+                Phase::LevelComplete => "Level Complete",
+                _ => "Game Over",
+            }
+            .into();
+
+            let lines = [
+                format!("Score: {}", model.total_score),
+                format!("Quotas: {}", model.quotas_completed),
+                format!("Days: {}", model.days_survived),
+                format!("Money: {}", model.money),
+            ];
+            for line in lines {
+                let pos = summary.cut_top(font_size * 1.0);
+                let text = context.state.get_root_or(|| TextWidget::new(""));
+                text.update(pos, context);
+                text.text = line.into();
+            }
+
+            summary.cut_top(font_size);
+
+            let pos = summary
+                .cut_top(font_size * 1.2)
+                .with_width(font_size * 5.0, 0.5);
+            let restart = context.state.get_root_or(|| ButtonWidget::new("Restart"));
+            restart.update(pos, context);
+            if restart.text.state.clicked {
+                actions.push(GameAction::Restart);
+            }
+        }
+
         // Left bar
         {
             let title = left_bar.cut_top(font_size * 2.0);
 
+            if let Some(status) = &self.editor_status {
+                for line in status {
+                    let pos = left_bar.cut_top(font_size * 1.0);
+                    let text = context.state.get_root_or(|| TextWidget::new(""));
+                    text.update(pos, context);
+                    text.text = line.clone().into();
+                }
+                left_bar.cut_top(font_size * 0.5);
+            }
+
             let pos = left_bar.cut_top(font_size * 1.0);
             let score = context.state.get_root_or(|| TextWidget::new("Quota"));
             score.update(pos, context);
@@ -51,13 +113,20 @@ impl GameUi {
             let pos = left_bar.cut_top(font_size * 1.0);
             let score = context.state.get_root_or(|| TextWidget::new("Day"));
             score.update(pos, context);
-            score.text = format!("Day: {}/3", model.quota_day).into();
+            score.text = format!("Day: {}/{}", model.quota_day, model.quota_days()).into();
 
             let pos = left_bar.cut_top(font_size * 1.0);
             let score = context.state.get_root_or(|| TextWidget::new("Score"));
             score.update(pos, context);
             score.text = format!("Score: {}", model.round_score).into();
 
+            if let Some(ghost) = &model.train.ghost {
+                let pos = left_bar.cut_top(font_size * 1.0);
+                let text = context.state.get_root_or(|| TextWidget::new("Ghost"));
+                text.update(pos, context);
+                text.text = format!("Ghost: {:.1}s", ghost.value().as_f32()).into();
+            }
+
             let mut right = left_bar
                 .cut_top(font_size * 1.0)
                 .with_width(font_size * 3.0, 0.5);
@@ -80,6 +149,88 @@ impl GameUi {
             if launch.text.state.clicked {
                 actions.push(GameAction::LaunchTrain);
             }
+
+            if self.can_continue {
+                left_bar.cut_top(font_size * 0.5);
+                let pos = left_bar
+                    .cut_top(font_size * 1.2)
+                    .with_width(font_size * 5.0, 0.5);
+                let button = context.state.get_root_or(|| ButtonWidget::new("Continue"));
+                button.update(pos, context);
+                if button.text.state.clicked {
+                    actions.push(GameAction::Continue);
+                }
+            }
+
+            left_bar.cut_top(font_size * 0.5);
+            let pos = left_bar.cut_top(font_size * 1.0);
+            let text = context.state.get_root_or(|| TextWidget::new("Hand"));
+            text.update(pos, context);
+            text.text = format!(
+                "Hand ({} draw, {} discard)",
+                model.draw_pile.len(),
+                model.discard_pile.len()
+            )
+            .into();
+
+            let kinds = [
+                RailKind::Straight,
+                RailKind::Left,
+                RailKind::Junction,
+                RailKind::Cross,
+            ];
+            for kind in kinds {
+                let count = model.hand.iter().filter(|&&card| card == kind).count();
+                if count == 0 {
+                    continue;
+                }
+                let pos = left_bar
+                    .cut_top(font_size * 1.2)
+                    .with_width(font_size * 6.0, 0.5);
+                left_bar.cut_top(font_size * 0.2);
+                let button = context.state.get_root_or(|| ButtonWidget::new(""));
+                button.text.text = format!("{:?} x{}", kind, count).into();
+                button.bg_color = if kind == self.selected_rail {
+                    context.theme().highlight
+                } else {
+                    Color::WHITE
+                };
+                button.update(pos, context);
+                if button.text.state.clicked {
+                    actions.push(GameAction::SelectRail(kind));
+                }
+            }
+
+            // Owned upgrades
+            let mut owned: Vec<(&Upgrade, usize)> = Vec::new();
+            for upgrade in &model.upgrades {
+                match owned.iter_mut().find(|(up, _)| *up == upgrade) {
+                    Some((_, count)) => *count += 1,
+                    None => owned.push((upgrade, 1)),
+                }
+            }
+            if !owned.is_empty() {
+                left_bar.cut_top(font_size * 0.5);
+                let pos = left_bar.cut_top(font_size * 1.0);
+                let text = context.state.get_root_or(|| TextWidget::new("Upgrades"));
+                text.update(pos, context);
+
+                let mut row = left_bar.cut_top(font_size * 1.0);
+                for (upgrade, count) in owned {
+                    let mut pos = row.cut_left(font_size * 2.0);
+                    let icon = pos.split_left(0.5);
+                    let widget = context
+                        .state
+                        .get_root_or(|| IconWidget::new(atlas.circle()));
+                    widget.texture = upgrade_icon(model, &context.context.assets, upgrade);
+                    widget.update(icon, context);
+                    let text = context
+                        .state
+                        .get_root_or(|| TextWidget::new("").aligned(vec2(0.0, 0.5)));
+                    text.update(pos, context);
+                    text.text = format!("{}", count).into();
+                }
+            }
         }
 
         // Shop
@@ -116,16 +267,7 @@ impl GameUi {
                     .state
                     .get_root_or(|| IconButtonWidget::new_normal(atlas.circle()));
                 button.can_click = item.can_purchase;
-                button.icon.texture = match item.upgrade {
-                    Upgrade::Resource(resource) => match resource {
-                        Resource::PlusCent => atlas.plus_cent(),
-                        Resource::GhostFuel => atlas.ghost_fuel(),
-                        _ => unimplemented!(),
-                    },
-                    Upgrade::Speed => atlas.speed(),
-                    Upgrade::Feather => atlas.feather(),
-                    Upgrade::Turning => atlas.spiral(),
-                };
+                button.icon.texture = upgrade_icon(model, &context.context.assets, &item.upgrade);
                 let hovered = button.state.hovered;
                 button.update(pos, context);
                 if button.state.clicked {
@@ -150,21 +292,11 @@ impl GameUi {
                 let text = context
                     .state
                     .get_root_or(|| TextWidget::new("").aligned(vec2(0.0, 0.5)));
-                text.text = match upgrade {
-                    Upgrade::Resource(resource) => match resource {
-                        Resource::PlusCent => "pays dividents for saved score and money",
-                        Resource::GhostFuel => {
-                            "got ghosted by the developer so it doesn't do anything"
-                        }
-                        _ => unimplemented!(),
-                    },
-                    Upgrade::Speed => "train goes brrr",
-                    Upgrade::Feather => {
-                        "light like a feather, sails far away (reduces slowdown effect)"
-                    }
-                    Upgrade::Turning => "oh how quickly the trains have turned",
-                }
-                .into();
+                text.text = model
+                    .config
+                    .upgrade(&upgrade)
+                    .map_or("", |config| config.description.as_str())
+                    .into();
                 text.update(pos, context);
             }
         }
@@ -172,3 +304,26 @@ impl GameUi {
         actions
     }
 }
+
+/// Icon of the upgrade, resources without an icon in the atlas use their own sprite.
+fn upgrade_icon(model: &Model, assets: &Assets, upgrade: &Upgrade) -> SubTexture {
+    let atlas = &assets.atlas;
+    match upgrade {
+        Upgrade::Resource(resource) => model
+            .config
+            .resources
+            .get(resource)
+            .and_then(|config| config.icon.as_deref())
+            .and_then(|icon| atlas.get(icon))
+            .or_else(|| {
+                assets.resources.get(resource).map(|sprite| SubTexture {
+                    texture: sprite.texture.clone(),
+                    uv: Aabb2::ZERO.extend_positive(vec2::splat(1.0)),
+                })
+            })
+            .unwrap_or_else(|| atlas.circle()),
+        Upgrade::Speed => atlas.speed(),
+        Upgrade::Feather => atlas.feather(),
+        Upgrade::Turning => atlas.spiral(),
+    }
+}
@@ -0,0 +1,94 @@
+#![allow(dead_code)]
+
+mod assets;
+mod context;
+mod game;
+mod menu;
+pub mod model;
+mod prelude;
+mod render;
+mod task;
+mod ui;
+mod util;
+
+use anyhow::Result;
+use geng::prelude::*;
+
+const OPTIONS_STORAGE: &str = "options";
+const RUN_STORAGE: &str = "run";
+
+pub const FIXED_FPS: f64 = 60.0;
+const GAME_RESOLUTION: vec2<usize> = vec2(4 * 90, 3 * 90);
+
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Seed for the run, random by default.
+    pub seed: Option<u64>,
+    /// Path to save the run replay to.
+    pub record: Option<std::path::PathBuf>,
+    /// Path to a replay to play back.
+    pub replay: Option<std::path::PathBuf>,
+    /// Path to a level to play instead of generated boards.
+    pub level: Option<std::path::PathBuf>,
+}
+
+pub async fn geng_main(geng: Geng, options: RunOptions) -> Result<()> {
+    let playback = options
+        .replay
+        .as_ref()
+        .map(model::Replay::load)
+        .transpose()?;
+
+    log::debug!("Initializing the loading screen...");
+    let loading_assets: Rc<assets::LoadingAssets> =
+        geng::asset::Load::load(geng.asset_manager(), &run_dir().join("assets"), &())
+            .await
+            .context("when loading assets")?;
+
+    let load_everything = load_everything(geng.clone());
+    let loading_screen = menu::LoadingScreen::new(&geng, loading_assets, load_everything).run();
+
+    let context = loading_screen
+        .await
+        .ok_or_else(|| anyhow::Error::msg("loading screen failed"))??;
+
+    let level: Option<model::Level> = match &options.level {
+        Some(path) => {
+            let level: model::Level = geng::asset::Load::load(geng.asset_manager(), path, &())
+                .await
+                .with_context(|| format!("when loading level {:?}", path))?;
+            level
+                .validate(&context.assets.config)
+                .with_context(|| format!("when validating level {:?}", path))?;
+            Some(level)
+        }
+        None => None,
+    };
+
+    log::debug!("Loading complete!");
+
+    let state = game::GameState::new(
+        context,
+        options.seed,
+        options.record,
+        playback,
+        level,
+        options.level,
+    );
+    geng.run_state(state).await;
+
+    Ok(())
+}
+
+async fn load_everything(geng: Geng) -> Result<context::Context> {
+    let manager = geng.asset_manager();
+
+    let assets = assets::Assets::load(manager).await?;
+    let assets = Rc::new(assets);
+
+    let context = context::Context::new(&geng, &assets)
+        .await
+        .expect("failed to initialize context");
+
+    Ok(context)
+}
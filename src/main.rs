@@ -1,30 +1,23 @@
-#![allow(dead_code)]
-
-mod assets;
-mod context;
-mod game;
-mod menu;
-mod model;
-mod prelude;
-mod render;
-mod task;
-mod ui;
-mod util;
-
-use anyhow::Result;
 use geng::prelude::*;
 
-const OPTIONS_STORAGE: &str = "options";
-
-const FIXED_FPS: f64 = 60.0;
-const GAME_RESOLUTION: vec2<usize> = vec2(4 * 90, 3 * 90);
-
 #[derive(clap::Parser)]
 struct Opts {
     #[clap(flatten)]
     geng: geng::CliArgs,
     #[clap(long)]
     log: Option<String>,
+    /// Seed for the run, random by default.
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Save the replay of the run to the given path.
+    #[clap(long)]
+    record: Option<std::path::PathBuf>,
+    /// Play back the replay from the given path.
+    #[clap(long)]
+    replay: Option<std::path::PathBuf>,
+    /// Play the level from the given path instead of generated boards.
+    #[clap(long)]
+    level: Option<std::path::PathBuf>,
 }
 
 fn main() {
@@ -58,10 +51,18 @@ fn main() {
     let mut options = geng::ContextOptions::default();
     options.with_cli(&opts.geng);
     options.window.title = "Nertplate".into();
-    options.fixed_delta_time = 1.0 / FIXED_FPS;
+    options.fixed_delta_time = 1.0 / trake::FIXED_FPS;
 
     Geng::run_with(&options, |geng| async move {
-        let main = geng_main(geng, opts);
+        let main = trake::geng_main(
+            geng,
+            trake::RunOptions {
+                seed: opts.seed,
+                record: opts.record,
+                replay: opts.replay,
+                level: opts.level,
+            },
+        );
 
         #[cfg(not(target_arch = "wasm32"))]
         let main = async_compat::Compat::new(main);
@@ -78,38 +79,3 @@ fn main() {
 
     log::info!("Please come back...");
 }
-
-async fn geng_main(geng: Geng, _opts: Opts) -> Result<()> {
-    log::debug!("Initializing the loading screen...");
-    let loading_assets: Rc<assets::LoadingAssets> =
-        geng::asset::Load::load(geng.asset_manager(), &run_dir().join("assets"), &())
-            .await
-            .context("when loading assets")?;
-
-    let load_everything = load_everything(geng.clone());
-    let loading_screen = menu::LoadingScreen::new(&geng, loading_assets, load_everything).run();
-
-    let context = loading_screen
-        .await
-        .ok_or_else(|| anyhow::Error::msg("loading screen failed"))??;
-
-    log::debug!("Loading complete!");
-
-    let state = game::GameState::new(context);
-    geng.run_state(state).await;
-
-    Ok(())
-}
-
-async fn load_everything(geng: Geng) -> Result<context::Context> {
-    let manager = geng.asset_manager();
-
-    let assets = assets::Assets::load(manager).await?;
-    let assets = Rc::new(assets);
-
-    let context = context::Context::new(&geng, &assets)
-        .await
-        .expect("failed to initialize context");
-
-    Ok(context)
-}
@@ -12,6 +12,14 @@ pub struct Collision {
     pub penetration: Coord,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct CastHit {
+    /// Fraction of the movement made before the hit.
+    pub time: Coord,
+    /// Normal vector pointing away from the body.
+    pub normal: vec2<Coord>,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(default)]
 pub struct Transform {
@@ -123,6 +131,12 @@ impl Collider {
 
     /// Return the collision info if the two colliders are intersecting.
     pub fn collide(&self, other: &Self) -> Option<Collision> {
+        self.collide_within(other, Coord::ZERO)
+    }
+
+    /// Return the collision info if the two colliders are closer than the margin.
+    /// The penetration is negative when the colliders do not intersect.
+    pub fn collide_within(&self, other: &Self, margin: Coord) -> Option<Collision> {
         let delta = (other.position - self.position).as_f32();
 
         let self_angle = self.rotation.as_radians().as_f32();
@@ -134,7 +148,7 @@ impl Collider {
             parry2d::math::Isometry::new(parry2d::na::Vector2::new(delta.x, delta.y), other_angle);
         let other_shape = other.shape.to_parry();
 
-        let prediction = 0.0;
+        let prediction = margin.as_f32();
         parry2d::query::contact(
             &self_iso,
             &*self_shape,
@@ -153,4 +167,43 @@ impl Collider {
             }
         })
     }
+
+    /// Move the collider by `delta` and find the first hit with the other collider.
+    /// Colliders that are already intersecting and moving apart do not hit.
+    pub fn cast(&self, delta: vec2<Coord>, other: &Self) -> Option<CastHit> {
+        let offset = (other.position - self.position).as_f32();
+        let delta = delta.as_f32();
+
+        let self_angle = self.rotation.as_radians().as_f32();
+        let self_iso = parry2d::math::Isometry::rotation(self_angle);
+        let self_shape = self.shape.to_parry();
+
+        let other_angle = other.rotation.as_radians().as_f32();
+        let other_iso = parry2d::math::Isometry::new(
+            parry2d::na::Vector2::new(offset.x, offset.y),
+            other_angle,
+        );
+        let other_shape = other.shape.to_parry();
+
+        let max_toi = 1.0;
+        let stop_at_penetration = false;
+        parry2d::query::time_of_impact(
+            &self_iso,
+            &parry2d::na::Vector2::new(delta.x, delta.y),
+            &*self_shape,
+            &other_iso,
+            &parry2d::na::Vector2::zeros(),
+            &*other_shape,
+            max_toi,
+            stop_at_penetration,
+        )
+        .unwrap()
+        .map(|toi| {
+            let normal = toi.normal1.into_inner();
+            CastHit {
+                time: Coord::new(toi.toi),
+                normal: vec2(normal.x, normal.y).map(Coord::new),
+            }
+        })
+    }
 }
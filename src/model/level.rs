@@ -0,0 +1,203 @@
+use super::*;
+
+/// Handcrafted board, replaces the random generation of every round.
+#[derive(geng::asset::Load, Debug, Clone, Serialize, Deserialize)]
+#[load(serde = "ron")]
+pub struct Level {
+    pub map_size: vec2<ICoord>,
+    pub depo: LevelDepo,
+    pub goal: LevelGoal,
+    /// Interior wall cells, the border walls are always built.
+    #[serde(default)]
+    pub walls: Vec<vec2<ICoord>>,
+    #[serde(default)]
+    pub resources: Vec<LevelResource>,
+    #[serde(default)]
+    pub rails: Vec<LevelRail>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelDepo {
+    /// Cell the train leaves the depo into.
+    pub exit: vec2<ICoord>,
+    /// Direction the train leaves in, in quarter turns counter-clockwise from the right.
+    pub rotation: usize,
+}
+
+/// Score to reach within the given number of days to complete the level.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelGoal {
+    pub score: Score,
+    pub days: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelResource {
+    pub position: vec2<ICoord>,
+    pub resource: ResourceId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelRail {
+    pub position: vec2<ICoord>,
+    pub orientation: RailOrientation,
+}
+
+impl Level {
+    /// Empty board with the depo in the middle of the left side.
+    pub fn empty(map_size: vec2<ICoord>) -> Self {
+        Self {
+            map_size,
+            depo: LevelDepo {
+                exit: vec2(1, (map_size.y + 1) / 2),
+                rotation: 0,
+            },
+            goal: LevelGoal { score: 15, days: 3 },
+            walls: Vec::new(),
+            resources: Vec::new(),
+            rails: Vec::new(),
+        }
+    }
+
+    pub fn is_inside(&self, position: vec2<ICoord>) -> bool {
+        (1..=self.map_size.x).contains(&position.x) && (1..=self.map_size.y).contains(&position.y)
+    }
+
+    /// Cells of the map covered by a depo of the given size, the exit cell excluded.
+    pub fn depo_cells(&self, depo_size: vec2<Coord>) -> Vec<vec2<ICoord>> {
+        let dir = [vec2(1, 0), vec2(0, 1), vec2(-1, 0), vec2(0, -1)][self.depo.rotation % 4];
+        let size = if dir.x == 0 {
+            vec2(depo_size.y, depo_size.x)
+        } else {
+            depo_size
+        };
+        let offset = r32(0.5) + depo_size.x / r32(2.0);
+        let center = self.depo.exit.map(|x| r32(x as f32)) - dir.map(|x| r32(x as f32)) * offset;
+        // Cells the depo only touches are not covered
+        let half = size / r32(2.0) - vec2::splat(r32(0.01));
+        let min = (center - half).map(|x| x.round().as_f32() as ICoord);
+        let max = (center + half).map(|x| x.round().as_f32() as ICoord);
+        (min.x..=max.x)
+            .flat_map(|x| (min.y..=max.y).map(move |y| vec2(x, y)))
+            .filter(|&position| self.is_inside(position))
+            .collect()
+    }
+
+    /// Remove everything from the cell.
+    pub fn clear_cell(&mut self, position: vec2<ICoord>) {
+        self.walls.retain(|&pos| pos != position);
+        self.resources.retain(|item| item.position != position);
+        self.rails.retain(|item| item.position != position);
+    }
+
+    pub fn set_wall(&mut self, position: vec2<ICoord>) {
+        if !self.is_inside(position) || position == self.depo.exit {
+            return;
+        }
+        self.clear_cell(position);
+        self.walls.push(position);
+    }
+
+    pub fn set_resource(&mut self, position: vec2<ICoord>, resource: ResourceId) {
+        if !self.is_inside(position) {
+            return;
+        }
+        self.clear_cell(position);
+        self.resources.push(LevelResource { position, resource });
+    }
+
+    pub fn set_rail(&mut self, position: vec2<ICoord>, orientation: RailOrientation) {
+        if !self.is_inside(position) {
+            return;
+        }
+        self.clear_cell(position);
+        self.rails.push(LevelRail {
+            position,
+            orientation,
+        });
+    }
+
+    /// Change the map size, dropping everything outside of it.
+    pub fn resize(&mut self, map_size: vec2<ICoord>) {
+        self.map_size = map_size.map(|x| x.max(3));
+        let map_size = self.map_size;
+        let inside = |pos: vec2<ICoord>| {
+            (1..=map_size.x).contains(&pos.x) && (1..=map_size.y).contains(&pos.y)
+        };
+        self.walls.retain(|&pos| inside(pos));
+        self.resources.retain(|item| inside(item.position));
+        self.rails.retain(|item| inside(item.position));
+        let exit = self.depo.exit;
+        self.depo.exit = vec2(exit.x.clamp(1, map_size.x), exit.y.clamp(1, map_size.y));
+    }
+
+    pub fn save_file(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let data = ron::ser::to_string_pretty(self, default())?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Load the level outside of the asset manager.
+    pub fn load_file(path: impl AsRef<std::path::Path>, config: &Config) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("when reading level {:?}", path))?;
+        let level: Self = ron::from_str(&data).context("when parsing the level")?;
+        level
+            .validate(config)
+            .with_context(|| format!("when validating level {:?}", path))?;
+        Ok(level)
+    }
+
+    /// Check that everything is inside the map, no two items share a cell,
+    /// the depo is not blocked, the resources are defined in the config,
+    /// and the goal can be reached.
+    pub fn validate(&self, config: &Config) -> Result<()> {
+        anyhow::ensure!(
+            self.map_size.x > 0 && self.map_size.y > 0,
+            "map size {:?} is empty",
+            self.map_size
+        );
+        anyhow::ensure!(self.goal.days > 0, "the goal gives no days");
+        anyhow::ensure!(
+            self.is_inside(self.depo.exit),
+            "depo exit {:?} is outside the map",
+            self.depo.exit
+        );
+        anyhow::ensure!(
+            !self.walls.contains(&self.depo.exit),
+            "depo exit {:?} is walled off",
+            self.depo.exit
+        );
+
+        let depo_cells = self.depo_cells(config.depo_size);
+        let cells = self
+            .walls
+            .iter()
+            .copied()
+            .chain(self.resources.iter().map(|item| item.position))
+            .chain(self.rails.iter().map(|item| item.position));
+        let mut occupied = HashSet::new();
+        for position in cells {
+            anyhow::ensure!(
+                self.is_inside(position),
+                "cell {:?} is outside the map",
+                position
+            );
+            anyhow::ensure!(
+                occupied.insert(position),
+                "cell {:?} has more than one item",
+                position
+            );
+            anyhow::ensure!(
+                !depo_cells.contains(&position),
+                "cell {:?} is under the depo",
+                position
+            );
+        }
+        for item in &self.resources {
+            config.check_resource(&format!("resource at {:?}", item.position), &item.resource)?;
+        }
+        Ok(())
+    }
+}
@@ -1,27 +1,89 @@
 use super::*;
 
 impl Model {
+    pub fn apply_action(&mut self, action: PlayerAction) {
+        match action {
+            PlayerAction::LaunchTrain => self.launch_train(),
+            PlayerAction::BuyShop(i) => self.buy_shop(i),
+            PlayerAction::PlaceRail {
+                position,
+                orientation,
+            } => self.place_rail(position, orientation),
+            PlayerAction::RemoveRail(position) => self.remove_rail(position),
+            PlayerAction::RotateRail(position) => self.rotate_rail(position),
+            PlayerAction::Undo => self.undo(),
+            PlayerAction::Redo => self.redo(),
+            PlayerAction::ToggleSwitch(position) => self.toggle_switch(position),
+            PlayerAction::ToggleAllSwitches => self.toggle_all_switches(),
+            PlayerAction::SpawnResource { position, resource } => {
+                self.spawn_resource(position, resource)
+            }
+        }
+    }
+
+    /// Put a resource into the cell if it is empty.
+    pub fn spawn_resource(&mut self, position: vec2<ICoord>, resource: ResourceId) {
+        if self.phase.is_over() || self.item_at(position).is_some() {
+            return;
+        }
+        self.insert_grid_item(GridItem {
+            position,
+            rail: None,
+            resource: Some(resource),
+            wall: None,
+        });
+    }
+
+    /// Flip the switch of the junction at the position.
+    pub fn toggle_switch(&mut self, position: vec2<ICoord>) {
+        if self.phase.is_over() {
+            return;
+        }
+        let Some(id) = self.grid_index.item_at(position) else {
+            return;
+        };
+        if let Some(rail) = get!(self.grid_items, id, (&mut rail.Get.Some)) {
+            if rail.orientation.kind == RailKind::Junction {
+                rail.switched = !rail.switched;
+            }
+        }
+    }
+
+    /// Flip the switches of all junctions on the map.
+    pub fn toggle_all_switches(&mut self) {
+        if self.phase.is_over() {
+            return;
+        }
+        for rail in query!(self.grid_items, (&mut rail.Get.Some)) {
+            if rail.orientation.kind == RailKind::Junction {
+                rail.switched = !rail.switched;
+            }
+        }
+    }
+
     pub fn buy_shop(&mut self, i: usize) {
+        if self.phase.is_over() {
+            return;
+        }
         if let Some(item) = self.shop.get_mut(i) {
             if item.can_purchase && self.money >= item.price {
                 self.money -= item.price;
                 item.can_purchase = false;
-                match item.upgrade {
-                    Upgrade::Resource(resource) => self.deck.resources.push(resource),
-                    Upgrade::Speed => {
-                        let mult = r32(1.2);
-                        self.config.train.rail_speed *= mult;
-                        self.config.train.offrail_speed *= mult;
-                    }
-                    Upgrade::Feather => {
-                        self.config.train.overtime_slowdown *= r32(0.9);
-                    }
-                    Upgrade::Turning => {
-                        let limit = Angle::from_radians(r32(4.0));
-                        let s = &mut self.config.train.turn_speed;
-                        *s = limit + (*s - limit) * r32(0.75);
-                    }
+                self.events.push(GameEvent::ShopPurchase {
+                    upgrade: item.upgrade.clone(),
+                    price: item.price,
+                });
+                self.upgrades.push(item.upgrade.clone());
+
+                let Some(config) = self.config.upgrade(&item.upgrade).cloned() else {
+                    log::warn!("Bought an unconfigured upgrade {:?}", item.upgrade);
+                    return;
+                };
+                if let Upgrade::Resource(resource) = &item.upgrade {
+                    self.deck.resources.push(resource.clone());
                 }
+                self.modifiers.extend(config.modifiers);
+                self.update_stats();
             }
         }
     }
@@ -29,24 +91,155 @@ impl Model {
     pub fn launch_train(&mut self) {
         let Phase::Setup = self.phase else { return };
 
-        let speed = self.config.train.rail_speed;
+        let speed = self.stats.rail_speed;
         self.train.target_speed = speed;
         self.train.train_speed = speed;
 
+        self.clear_setup_history();
         self.phase = Phase::Resolution;
-        self.context.play_sfx(&self.context.assets.sounds.choochoo);
+        self.events.push(GameEvent::TrainLaunched);
     }
 
     pub fn place_rail(&mut self, position: vec2<ICoord>, orientation: RailOrientation) {
-        if query!(self.grid_items, (&position)).any(|&pos| pos == position) {
+        if !self.put_rail(position, orientation) {
+            return;
+        }
+        if let Phase::Setup = self.phase {
+            self.undo_stack.push(SetupEdit::Place {
+                position,
+                orientation,
+            });
+            self.redo_stack.clear();
+        }
+    }
+
+    /// Pick up a player-placed rail, returning its card to the hand.
+    pub fn remove_rail(&mut self, position: vec2<ICoord>) {
+        let Phase::Setup = self.phase else { return };
+        if let Some(orientation) = self.take_rail(position) {
+            self.undo_stack.push(SetupEdit::Remove {
+                position,
+                orientation,
+            });
+            self.redo_stack.clear();
+        }
+    }
+
+    /// Rotate a player-placed rail counter-clockwise.
+    pub fn rotate_rail(&mut self, position: vec2<ICoord>) {
+        let Phase::Setup = self.phase else { return };
+        let Some(from) = self
+            .placed_rail_mut(position)
+            .map(|rail| rail.orientation.rotation)
+        else {
             return;
+        };
+        let to = (from + 1) % 4;
+        self.set_rail_rotation(position, to);
+        self.undo_stack
+            .push(SetupEdit::Rotate { position, from, to });
+        self.redo_stack.clear();
+    }
+
+    pub fn undo(&mut self) {
+        let Phase::Setup = self.phase else { return };
+        let Some(edit) = self.undo_stack.pop() else {
+            return;
+        };
+        match edit {
+            SetupEdit::Place { position, .. } => {
+                self.take_rail(position);
+            }
+            SetupEdit::Remove {
+                position,
+                orientation,
+            } => {
+                self.put_rail(position, orientation);
+            }
+            SetupEdit::Rotate { position, from, .. } => self.set_rail_rotation(position, from),
         }
+        self.redo_stack.push(edit);
+    }
 
-        self.grid_items.insert(GridItem {
+    pub fn redo(&mut self) {
+        let Phase::Setup = self.phase else { return };
+        let Some(edit) = self.redo_stack.pop() else {
+            return;
+        };
+        match edit {
+            SetupEdit::Place {
+                position,
+                orientation,
+            } => {
+                self.put_rail(position, orientation);
+            }
+            SetupEdit::Remove { position, .. } => {
+                self.take_rail(position);
+            }
+            SetupEdit::Rotate { position, to, .. } => self.set_rail_rotation(position, to),
+        }
+        self.undo_stack.push(edit);
+    }
+
+    /// Forget the edits of the setup phase.
+    pub fn clear_setup_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    /// Whether a rail of the kind can be placed from the hand at the position.
+    pub fn can_place_rail(&self, position: vec2<ICoord>, kind: RailKind) -> bool {
+        !self.phase.is_over()
+            && self.is_inside_map(position)
+            && self.item_at(position).is_none()
+            && self.hand.contains(&kind)
+    }
+
+    /// Place a rail from the hand, returns whether it was placed.
+    fn put_rail(&mut self, position: vec2<ICoord>, orientation: RailOrientation) -> bool {
+        if !self.can_place_rail(position, orientation.kind) {
+            return false;
+        }
+        let Some(card) = self.hand.iter().position(|&kind| kind == orientation.kind) else {
+            return false;
+        };
+        let card = self.hand.remove(card);
+        self.discard_pile.push(card);
+
+        self.insert_grid_item(GridItem {
             position,
-            rail: Some(Rail { orientation }),
+            rail: Some(Rail {
+                player_placed: true,
+                ..Rail::new(orientation)
+            }),
             resource: None,
             wall: None,
         });
+        true
+    }
+
+    /// Remove a player-placed rail and return its card to the hand.
+    fn take_rail(&mut self, position: vec2<ICoord>) -> Option<RailOrientation> {
+        let orientation = self.placed_rail_mut(position)?.orientation;
+        let id = self.item_at(position)?;
+        self.remove_grid_item(id);
+
+        let kind = orientation.kind;
+        if let Some(card) = self.discard_pile.iter().rposition(|&card| card == kind) {
+            self.discard_pile.remove(card);
+        }
+        self.hand.push(kind);
+        Some(orientation)
+    }
+
+    fn set_rail_rotation(&mut self, position: vec2<ICoord>, rotation: usize) {
+        if let Some(rail) = self.placed_rail_mut(position) {
+            rail.orientation.rotation = rotation;
+        }
+    }
+
+    fn placed_rail_mut(&mut self, position: vec2<ICoord>) -> Option<&mut Rail> {
+        let id = self.item_at(position)?;
+        get!(self.grid_items, id, (&mut rail.Get.Some)).filter(|rail| rail.player_placed)
     }
 }
@@ -0,0 +1,325 @@
+mod walls;
+
+use super::*;
+
+impl Model {
+    pub fn init(&mut self) {
+        // Camera
+        self.camera.center = self
+            .grid
+            .grid_to_world(self.config.map_size / 2 + vec2(1, 1))
+            .as_f32();
+
+        // Walls
+        self.grid_items = default();
+        self.grid_index = default();
+        let map_size = self.config.map_size;
+        for x in 0..=map_size.x + 1 {
+            self.insert_wall(vec2(x, 0));
+            self.insert_wall(vec2(x, map_size.y + 1));
+        }
+        for y in 1..=map_size.y {
+            self.insert_wall(vec2(0, y));
+            self.insert_wall(vec2(map_size.x + 1, y));
+        }
+
+        // Rail cards
+        self.hand.clear();
+        self.discard_pile.clear();
+        self.draw_pile = self.deck.rails.clone();
+        self.draw_pile.shuffle(&mut self.rng);
+
+        self.next_round();
+    }
+
+    pub fn insert_wall(&mut self, position: vec2<ICoord>) -> GridItemId {
+        let collider = Collider::aabb(
+            Aabb2::point(self.grid.grid_to_world(position))
+                .extend_symmetric(self.grid.cell_size * r32(0.9 / 2.0)),
+        );
+        self.insert_grid_item(GridItem {
+            position,
+            rail: None,
+            resource: None,
+            wall: Some(Wall { collider }),
+        })
+    }
+
+    /// Put a fresh locomotive into the depo.
+    pub fn reset_train(&mut self) {
+        self.train = Train {
+            in_depo: true,
+            target_speed: r32(0.0),
+            train_speed: r32(0.0),
+            ghost: None,
+            recoil: None,
+            blocks: vec![self.depo_locomotive()].into(),
+        };
+    }
+
+    /// Locomotive standing in the depo, facing the exit.
+    pub fn depo_locomotive(&self) -> TrainBlock {
+        let mut block = TrainBlock::new_locomotive(&self.stats, self.depo.position);
+        block.collider.rotation = self.depo.rotation;
+        block
+    }
+
+    /// Number of days given to complete a quota.
+    pub fn quota_days(&self) -> usize {
+        match &self.level {
+            Some(level) => level.goal.days,
+            None => self.config.quota.days,
+        }
+    }
+
+    /// Discard the current hand and draw a new one from the draw pile.
+    pub fn draw_hand(&mut self) {
+        self.clear_setup_history();
+        self.discard_pile.append(&mut self.hand);
+        for _ in 0..self.config.hand_size {
+            if self.draw_pile.is_empty() {
+                // Reshuffle the discard pile
+                std::mem::swap(&mut self.draw_pile, &mut self.discard_pile);
+                self.draw_pile.shuffle(&mut self.rng);
+            }
+            let Some(card) = self.draw_pile.pop() else {
+                break;
+            };
+            self.hand.push(card);
+        }
+    }
+
+    pub fn next_round(&mut self) {
+        log::debug!("Round ended");
+        self.round_time = FloatTime::ZERO;
+
+        // Score
+        self.quota_day += 1;
+        if self.quota_day == 1 && self.quotas_completed == 0 {
+            // First quota
+            self.current_quota = match &self.level {
+                Some(level) => level.goal.score,
+                None => self.config.quota.first,
+            };
+        } else {
+            self.days_survived += 1;
+            self.total_score += self.round_score;
+            self.quota_score += self.round_score;
+            self.events.push(GameEvent::RoundEnded {
+                score: self.round_score,
+            });
+            if self.quota_score >= self.current_quota {
+                // Next quota
+                self.events.push(GameEvent::QuotaCompleted {
+                    quota: self.current_quota,
+                    score: self.quota_score,
+                });
+                self.quotas_completed += 1;
+                if self.level.is_some() {
+                    // Level complete
+                    self.round_score = 0;
+                    self.train.target_speed = Coord::ZERO;
+                    self.train.train_speed = Coord::ZERO;
+                    self.phase = Phase::LevelComplete;
+                    self.events.push(GameEvent::LevelCompleted {
+                        total_score: self.total_score,
+                    });
+                    return;
+                }
+                let config = &self.config.quota;
+                self.money += (self.quota_score as f32 * config.quota_payout.as_f32()) as Money;
+                let noise = self.rng.gen_range(config.growth_noise.clone()).as_f32();
+                let growth = config.growth.as_f32()
+                    * (self.quotas_completed as f32).powf(config.growth_power.as_f32());
+                self.current_quota += (growth * noise) as Score;
+                self.quota_score = 0;
+                self.quota_day = 1;
+            } else if self.quota_day > self.quota_days() {
+                // Quota failed
+                log::debug!("Quota failed, final score: {}", self.total_score);
+                self.round_score = 0;
+                self.train.target_speed = Coord::ZERO;
+                self.train.train_speed = Coord::ZERO;
+                self.phase = Phase::GameOver;
+                self.events.push(GameEvent::GameOver {
+                    total_score: self.total_score,
+                });
+                return;
+            }
+        }
+        let config = &self.config.quota;
+        let noise = self.rng.gen_range(config.round_noise.clone()).as_f32();
+        let bonus = self.rng.gen_range(config.round_bonus.clone()).as_f32();
+        self.money += (self.round_score as f32 * config.round_payout.as_f32() * noise + bonus)
+            .round()
+            .max(0.0) as Money;
+        self.round_score = 0;
+
+        // Depo
+        if let Some(level) = &self.level {
+            self.depo = self.level_depo(&level.depo);
+        } else {
+            self.random_depo();
+        }
+
+        self.reset_train();
+        self.debris.clear();
+
+        // Cleanup everything but the border walls
+        let ids: Vec<_> = query!(self.grid_items, (id, &position, &wall))
+            .filter(|(_, &pos, wall)| wall.is_none() || self.is_inside_map(pos))
+            .map(|(id, _, _)| id)
+            .collect();
+        for id in ids {
+            self.remove_grid_item(id);
+        }
+
+        if let Some(level) = self.level.clone() {
+            self.spawn_level(&level);
+        } else {
+            self.spawn_items();
+        }
+        self.draw_hand();
+
+        self.roll_shop();
+        self.phase = Phase::Setup;
+    }
+
+    fn random_depo(&mut self) {
+        let size = self.config.depo_size;
+        let grid_min = self.grid.gridf_to_world(vec2(0.5, 0.5).as_r32());
+        let grid_max = self
+            .grid
+            .gridf_to_world(self.config.map_size.map(|x| x as f32 - 0.5).as_r32());
+        let y = self.rng.gen_range(grid_min.y..=grid_max.y - size.y);
+        self.depo = Collider::aabb(
+            Aabb2::point(vec2(grid_min.x, y))
+                .extend_left(size.x)
+                .extend_up(size.y),
+        );
+    }
+
+    /// Depo that lets the train out into the exit cell.
+    fn level_depo(&self, depo: &LevelDepo) -> Collider {
+        let size = self.config.depo_size;
+        let rotation = Angle::from_degrees(r32(90.0 * (depo.rotation % 4) as f32));
+        let exit = self.grid.grid_to_world(depo.exit);
+        let offset = self.grid.cell_size.x / r32(2.0) + size.x / r32(2.0);
+        Collider {
+            shape: Shape::rectangle(size),
+            position: exit - rotation.unit_vec() * offset,
+            rotation,
+        }
+    }
+
+    fn spawn_level(&mut self, level: &Level) {
+        for &position in &level.walls {
+            self.insert_wall(position);
+        }
+        for item in &level.resources {
+            self.insert_grid_item(GridItem {
+                position: item.position,
+                rail: None,
+                resource: Some(item.resource.clone()),
+                wall: None,
+            });
+        }
+        for item in &level.rails {
+            self.insert_grid_item(GridItem {
+                position: item.position,
+                rail: Some(Rail::new(item.orientation)),
+                resource: None,
+                wall: None,
+            });
+        }
+    }
+
+    fn spawn_items(&mut self) {
+        // Interior walls
+        let mut positions = self.generate_walls();
+
+        // Spawn items
+        let exit = self.depo_exit();
+        positions.retain(|pos| !exit.contains(pos));
+        positions.shuffle(&mut self.rng);
+
+        for res in self.deck.resources.clone() {
+            if let Some(position) = positions.pop() {
+                self.insert_grid_item(GridItem {
+                    position,
+                    rail: None,
+                    resource: Some(res),
+                    wall: None,
+                });
+            }
+        }
+
+        // Bonus resources
+        let mut options: Vec<(ResourceId, f32)> = self
+            .config
+            .resources
+            .iter()
+            .filter(|(_, config)| config.rarity > R32::ZERO)
+            .map(|(res, config)| (res.clone(), 1.0 / config.rarity.as_f32()))
+            .collect();
+        options.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let count = self.rng.gen_range(self.config.bonus_resources.clone());
+        for _ in 0..count {
+            let Ok((res, _)) = options.choose_weighted(&mut self.rng, |&(_, weight)| weight) else {
+                break;
+            };
+            if let Some(position) = positions.pop() {
+                self.insert_grid_item(GridItem {
+                    position,
+                    rail: None,
+                    resource: Some(res.clone()),
+                    wall: None,
+                });
+            }
+        }
+    }
+
+    fn roll_shop(&mut self) {
+        let config = &self.config.shop;
+        let options: Vec<&UpgradeConfig> = config
+            .upgrades
+            .iter()
+            .filter(|option| {
+                let stacks = self
+                    .upgrades
+                    .iter()
+                    .filter(|&up| *up == option.upgrade)
+                    .count();
+                match option.max_stacks {
+                    Some(max) => stacks < max,
+                    None => true,
+                }
+            })
+            .collect();
+
+        let discount = config
+            .discounts
+            .choose_weighted(&mut self.rng, |option| option.weight.as_f32())
+            .map_or(0.0, |option| option.discount.as_f32());
+        let discount_i = self.rng.gen_range(0..config.slots.max(1));
+        let offers: Vec<&UpgradeConfig> = options
+            .choose_multiple_weighted(&mut self.rng, config.slots, |option| option.weight.as_f32())
+            .map(|offers| offers.copied().collect())
+            .unwrap_or_default();
+        self.shop = offers
+            .into_iter()
+            .enumerate()
+            .map(|(i, option)| {
+                let mut price = option.price;
+                if i == discount_i {
+                    price -= (price as f32 * discount).ceil() as Money;
+                }
+                ShopItem {
+                    upgrade: option.upgrade.clone(),
+                    price,
+                    can_purchase: true,
+                }
+            })
+            .collect();
+    }
+}
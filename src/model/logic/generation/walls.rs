@@ -0,0 +1,159 @@
+use super::*;
+
+/// Attempts at generating a layout before falling back to an empty map.
+const LAYOUT_ATTEMPTS: usize = 10;
+
+impl Model {
+    /// Cells the train leaves the depo through.
+    pub fn depo_exit(&self) -> Vec<vec2<ICoord>> {
+        let aabb = self.depo.compute_aabb();
+        let min = self.grid.world_to_grid(aabb.min).y.max(1);
+        let max = self
+            .grid
+            .world_to_grid(aabb.max)
+            .y
+            .min(self.config.map_size.y);
+        (min..=max).map(|y| vec2(1, y)).collect()
+    }
+
+    /// Generate interior walls for the round.
+    /// Returns the free cells reachable from the depo.
+    pub fn generate_walls(&mut self) -> Vec<vec2<ICoord>> {
+        for _ in 0..LAYOUT_ATTEMPTS {
+            let walls = self.generate_layout();
+            let reachable = self.reachable_cells(&walls);
+            let area = self.config.map_size.x * self.config.map_size.y;
+            if reachable.len() as f32 >= self.config.walls.min_reachable.as_f32() * area as f32 {
+                let mut walls: Vec<_> = walls.into_iter().collect();
+                walls.sort_by_key(|pos| (pos.x, pos.y));
+                for position in walls {
+                    self.insert_wall(position);
+                }
+                return reachable;
+            }
+        }
+
+        log::debug!("Failed to generate a wall layout, leaving the map empty");
+        self.reachable_cells(&HashSet::new())
+    }
+
+    fn generate_layout(&mut self) -> HashSet<vec2<ICoord>> {
+        let config = self.config.walls.clone();
+        let map_size = self.config.map_size;
+        let scale = 1.0 + config.growth_per_quota.as_f32() * self.quotas_completed as f32;
+        let count = |n: usize| (n as f32 * scale).round() as usize;
+
+        let mut walls = HashSet::new();
+        let random_cell =
+            |rng: &mut StdRng| vec2(rng.gen_range(1..=map_size.x), rng.gen_range(1..=map_size.y));
+
+        // Pillars
+        for _ in 0..count(config.pillars) {
+            walls.insert(random_cell(&mut self.rng));
+        }
+
+        // Corridors
+        for _ in 0..count(config.corridors) {
+            let start = random_cell(&mut self.rng);
+            let length = self.rng.gen_range(config.corridor_length.clone());
+            let (dir, side) = if self.rng.gen() {
+                (vec2(1, 0), vec2(0, config.corridor_width + 1))
+            } else {
+                (vec2(0, 1), vec2(config.corridor_width + 1, 0))
+            };
+            for i in 0..length {
+                let pos = start + dir * i;
+                walls.insert(pos);
+                walls.insert(pos + side);
+            }
+        }
+
+        // Rooms
+        for _ in 0..count(config.rooms) {
+            let min = random_cell(&mut self.rng);
+            let size = vec2(
+                self.rng.gen_range(config.room_size.clone()),
+                self.rng.gen_range(config.room_size.clone()),
+            );
+            let max = min + size - vec2(1, 1);
+
+            // Doorway in the middle of a random side
+            let (horizontal, fixed) = match self.rng.gen_range(0..4) {
+                0 => (false, max.x),
+                1 => (true, max.y),
+                2 => (false, min.x),
+                _ => (true, min.y),
+            };
+            let (from, to) = if horizontal {
+                (min.x, max.x)
+            } else {
+                (min.y, max.y)
+            };
+            let start = (from + to + 1 - config.doorway_width) / 2;
+            let doorway: Vec<_> = (start..start + config.doorway_width)
+                .map(|i| {
+                    if horizontal {
+                        vec2(i, fixed)
+                    } else {
+                        vec2(fixed, i)
+                    }
+                })
+                .collect();
+
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    let pos = vec2(x, y);
+                    let edge = x == min.x || x == max.x || y == min.y || y == max.y;
+                    if edge && !doorway.contains(&pos) {
+                        walls.insert(pos);
+                    }
+                }
+            }
+        }
+
+        // Keep inside the map and clear the depo exit
+        let clearance: Vec<_> = self
+            .depo_exit()
+            .into_iter()
+            .flat_map(|pos| (0..config.depo_clearance).map(move |x| pos + vec2(x, 0)))
+            .collect();
+        walls.retain(|&pos| self.is_inside_map(pos) && !clearance.contains(&pos));
+
+        // Limit the coverage
+        let max_walls =
+            (config.max_coverage.as_f32() * (map_size.x * map_size.y) as f32).floor() as usize;
+        if walls.len() > max_walls {
+            let mut cells: Vec<_> = walls.into_iter().collect();
+            cells.sort_by_key(|pos| (pos.x, pos.y));
+            cells.shuffle(&mut self.rng);
+            cells.truncate(max_walls);
+            walls = cells.into_iter().collect();
+        }
+
+        walls
+    }
+
+    /// Free cells reachable from the depo exit, in the order they were found.
+    fn reachable_cells(&self, walls: &HashSet<vec2<ICoord>>) -> Vec<vec2<ICoord>> {
+        let is_free = |pos: vec2<ICoord>| self.is_inside_map(pos) && !walls.contains(&pos);
+
+        let mut visited: HashSet<vec2<ICoord>> = HashSet::new();
+        let mut queue: VecDeque<vec2<ICoord>> = VecDeque::new();
+        let mut reachable = Vec::new();
+        for pos in self.depo_exit() {
+            if is_free(pos) && visited.insert(pos) {
+                queue.push_back(pos);
+            }
+        }
+        while let Some(pos) = queue.pop_front() {
+            reachable.push(pos);
+            for dir in [vec2(1, 0), vec2(0, 1), vec2(-1, 0), vec2(0, -1)] {
+                let next = pos + dir;
+                if is_free(next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        reachable
+    }
+}
@@ -1,104 +1,51 @@
 mod actions;
 mod generation;
+mod prediction;
 
 use super::*;
 
+/// Longest distance the head moves in one step, relative to the cell size.
+const MAX_HEAD_STEP: f32 = 0.25;
+
+/// Distance at which the head counts as touching a wall.
+const CONTACT_MARGIN: f32 = 0.01;
+
 impl Model {
     pub fn update(&mut self, delta_time: FloatTime, player_input: PlayerInput) {
         self.real_time += delta_time;
 
-        self.context
-            .music
-            .set_volume(self.train.train_speed.as_f32().clamp(0.0, 1.0));
-
         match self.phase {
-            Phase::Setup => {}
+            Phase::Setup | Phase::GameOver | Phase::LevelComplete => {}
             Phase::Resolution => {
                 self.round_time += delta_time;
+                self.update_ghost(delta_time);
+                self.update_recoil(delta_time);
+                self.update_debris(delta_time);
                 self.move_train(delta_time, &player_input);
                 self.collect_resources(delta_time);
                 self.collide_train(delta_time);
             }
         }
-
-        self.passive_particles(delta_time);
-        self.process_particles(delta_time);
-    }
-
-    fn passive_particles(&mut self, _delta_time: FloatTime) {
-        for wall in query!(self.grid_items, (&wall.Get.Some)) {
-            if wall.collider.check(&self.depo) {
-                continue;
-            }
-            self.particles_queue.push(SpawnParticles {
-                kind: ParticleKind::Wall,
-                density: r32(0.5),
-                distribution: ParticleDistribution::Aabb(wall.collider.compute_aabb()),
-                size: r32(0.05)..=r32(0.1),
-                ..default()
-            });
-        }
-    }
-
-    fn process_particles(&mut self, delta_time: FloatTime) {
-        // Floating texts
-        let mut dead_ids = Vec::new();
-        for (id, position, velocity, lifetime) in query!(
-            self.floating_texts,
-            (id, &mut position, &velocity, &mut lifetime)
-        ) {
-            *position += *velocity * delta_time;
-            lifetime.change(-delta_time);
-            if lifetime.is_min() {
-                dead_ids.push(id);
-            }
-        }
-        for id in dead_ids {
-            self.floating_texts.remove(id);
-        }
-
-        // Particles
-        let mut dead_ids = Vec::new();
-        for (id, position, velocity, lifetime) in query!(
-            self.particles,
-            (id, &mut position, &velocity, &mut lifetime)
-        ) {
-            *position += *velocity * delta_time;
-            lifetime.change(-delta_time);
-            if lifetime.is_min() {
-                dead_ids.push(id);
-            }
-        }
-        for id in dead_ids {
-            self.particles.remove(id);
-        }
-        let spawn = self.particles_queue.drain(..).flat_map(spawn_particles);
-        for particle in spawn {
-            self.particles.insert(particle);
-        }
     }
 
     fn collect_resources(&mut self, _delta_time: FloatTime) {
-        let mut rng = thread_rng();
-
         let mut collected = Vec::new();
         for wagon in &self.train.blocks {
             let grid_pos = self.grid.world_to_grid(wagon.collider.position);
-            for (res_id, &res_pos, _res) in
-                query!(self.grid_items, (id, &position, &resource.Get.Some))
+            if self
+                .grid_index
+                .resource_at(&self.grid_items, grid_pos)
+                .is_some()
             {
-                if grid_pos == res_pos {
-                    collected.push(res_id);
-                }
+                collected.extend(self.grid_index.item_at(grid_pos));
             }
         }
 
         if !collected.is_empty() {
             self.add_wagon(TrainBlockKind::Wagon);
-            self.context.play_sfx(&self.context.assets.sounds.clop2);
         }
         for id in collected {
-            if let Some(item) = self.grid_items.remove(id) {
+            if let Some(item) = self.remove_grid_item(id) {
                 if let Some(res) = item.resource {
                     log::debug!("Collected: {:?}", res);
                     let position = self.grid.grid_to_world(item.position);
@@ -106,49 +53,44 @@ impl Model {
                     let mut plus_score = 0;
                     let mut plus_money = 0;
 
-                    match res {
-                        Resource::PlusCent => {
-                            plus_score += self.round_score / 5;
-                            plus_money += self.money / 10;
+                    if let Some(config) = self.config.resources.get(&res) {
+                        plus_score += config.value;
+                        if let Some(share) = config.score_share {
+                            plus_score += (self.round_score as f32 * share.as_f32()) as Score;
                         }
-                        Resource::Coin => {
-                            plus_money += rng.gen_range(8..=13);
+                        if let Some(payout) = &config.payout {
+                            plus_money += self.rng.gen_range(payout.clone());
                         }
-                        Resource::GhostFuel => {
-                            // TODO
+                        if let Some(share) = config.money_share {
+                            plus_money += (self.money as f32 * share.as_f32()) as Money;
                         }
-                        _ => (),
-                    }
-
-                    if let Some(config) = self.config.resources.get(&res) {
-                        plus_score += config.value;
+                        match config.effect {
+                            Some(ResourceEffect::Ghost) => {
+                                self.train.ghost =
+                                    Some(Bounded::new_max(self.stats.ghost_duration));
+                            }
+                            None => (),
+                        }
+                    } else {
+                        log::warn!("Collected an unknown resource: {:?}", res);
                     }
 
                     self.round_score += plus_score;
                     self.money += plus_money;
 
-                    self.particles_queue.push(SpawnParticles {
-                        kind: ParticleKind::Collect(res),
-                        density: r32(10.0),
-                        distribution: ParticleDistribution::Circle {
-                            center: position,
-                            radius: r32(0.5),
-                        },
-                        velocity: vec2(0.0, 1.0).as_r32(),
-                        ..default()
+                    self.events.push(GameEvent::ResourceCollected {
+                        resource: res,
+                        position,
+                        score: plus_score,
+                        money: plus_money,
                     });
-
-                    if plus_score != 0 {
-                        self.floating_texts
-                            .insert(spawn_text(format!("{:+}", plus_score), position));
-                    }
                 }
             }
         }
     }
 
     fn collide_train(&mut self, _delta_time: FloatTime) {
-        let Some(head) = self.train.blocks.front_mut() else {
+        let Some(head) = self.train.blocks.front() else {
             return;
         };
 
@@ -161,40 +103,228 @@ impl Model {
         }
         self.train.in_depo = false;
 
-        let mut collision = false;
-        for wall in query!(self.grid_items, (&wall.Get.Some)) {
-            if head.collider.check(&wall.collider) {
-                collision = true;
-                break;
+        if let Some(i) = self.self_collision() {
+            match self.stats.self_collision {
+                SelfCollision::LoseTail => {
+                    for block in self.train.blocks.drain(i..) {
+                        self.events.push(GameEvent::WagonDestroyed {
+                            position: block.collider.position,
+                            velocity: block.collider.rotation.unit_vec() * self.train.train_speed,
+                            score: 0,
+                        });
+                    }
+                }
+                SelfCollision::EndRound => {
+                    self.next_round();
+                    return;
+                }
             }
         }
 
-        if collision {
-            let block = self.train.blocks.pop_front().unwrap();
-            let plus_score =
-                -(self.round_score as f32 * thread_rng().gen_range(0.15..=0.25)).ceil() as Score;
-            self.round_score += plus_score;
-            if plus_score != 0 {
-                self.floating_texts.insert(spawn_text(
-                    format!("{:+}", plus_score),
-                    block.collider.position,
-                ));
-            }
+        let Some(head) = self.train.blocks.front() else {
+            return;
+        };
+
+        if let Some(collision) = self.wall_collision(&head.collider) {
+            self.derail(collision);
+        }
+    }
+
+    /// Bounce the locomotive off the wall it ran into, losing speed, score, and wagons
+    /// depending on the impact speed.
+    fn derail(&mut self, collision: Collision) {
+        let config = self.config.derail.clone();
+        let speed = self.train.train_speed;
+        let Some(head) = self.train.blocks.front_mut() else {
+            return;
+        };
+
+        let velocity = head.collider.rotation.unit_vec() * speed;
+        let impact = vec2::dot(velocity, collision.normal).max(Coord::ZERO);
+        if impact == Coord::ZERO && collision.penetration <= Coord::ZERO {
+            // Only touching the wall
+            return;
+        }
 
-            self.particles_queue.push(SpawnParticles {
-                kind: ParticleKind::WagonDestroyed,
-                density: r32(20.0),
-                distribution: ParticleDistribution::Circle {
-                    center: block.collider.position,
-                    radius: r32(0.5),
-                },
-                size: r32(0.1)..=r32(0.15),
-                velocity: -block.collider.rotation.unit_vec()
-                    * (self.train.train_speed * r32(0.5)).clamp(r32(0.5), r32(1.0)),
-                ..default()
+        // Push out of the wall
+        head.collider.position -= collision.normal * collision.penetration.max(Coord::ZERO);
+        let penalized = impact > config.min_impact;
+        let velocity = if penalized {
+            // Bounce off the wall
+            (velocity - collision.normal * impact * (Coord::ONE + config.bounce))
+                * (Coord::ONE - config.speed_loss)
+        } else {
+            // Slide along the wall
+            velocity - collision.normal * impact
+        };
+        if velocity != vec2::ZERO {
+            head.collider.rotation = velocity.arg();
+        }
+        head.snapped_to_rail = false;
+        head.entering_rail = false;
+        head.path.push_front(head.collider.position);
+        let position = head.collider.position;
+        self.train.train_speed = velocity.len();
+
+        if !penalized {
+            return;
+        }
+        // The bounce can turn the locomotive back over its own wagons
+        self.train.recoil = Some(Bounded::new_max(config.recoil));
+
+        let penalty = ((impact - config.min_impact) * config.penalty).min(config.max_penalty);
+        let plus_score = -(self.round_score as f32 * penalty.as_f32()).ceil() as Score;
+        self.round_score += plus_score;
+
+        // Detach wagons from the end
+        let detached = ((impact - config.detach_speed) * config.detach_per_speed)
+            .floor()
+            .max(Coord::ZERO)
+            .as_f32() as usize;
+        let keep = self.train.blocks.len().saturating_sub(detached).max(1);
+        let detached = self.train.blocks.len() - keep;
+        for block in self.train.blocks.drain(keep..) {
+            let spread = Angle::from_degrees(r32(self.rng.gen_range(-30.0..=30.0)));
+            self.debris.push(Debris {
+                velocity: (block.collider.rotation + spread).unit_vec() * speed,
+                collider: block.collider,
             });
+        }
+
+        self.events.push(GameEvent::Derailed {
+            position,
+            velocity: collision.normal * impact,
+            score: plus_score,
+            detached,
+        });
+    }
+
+    fn update_debris(&mut self, delta_time: FloatTime) {
+        let friction = self.config.derail.debris_friction;
+        let mut debris = std::mem::take(&mut self.debris);
+        for piece in &mut debris {
+            let speed = piece.velocity.len();
+            if speed == Coord::ZERO {
+                continue;
+            }
+            let new_speed = (speed - friction * delta_time).max(Coord::ZERO);
+            piece.velocity = piece.velocity * (new_speed / speed);
+
+            let old_position = piece.collider.position;
+            piece.collider.position += piece.velocity * delta_time;
+            if self.hits_wall(&piece.collider) {
+                // Stop at the wall
+                piece.collider.position = old_position;
+                piece.velocity = vec2::ZERO;
+            }
+        }
+        self.debris = debris;
+    }
+
+    /// Index of the first wagon the locomotive runs into.
+    /// None while the locomotive recoils from a wall.
+    fn self_collision(&self) -> Option<usize> {
+        if self.train.recoil.is_some() {
+            return None;
+        }
+        let head = self.train.blocks.front()?;
+        let skip = 1 + self.stats.self_collision_skip;
+        self.train
+            .blocks
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .find(|(_, block)| head.collider.check(&block.collider))
+            .map(|(i, _)| i)
+    }
+
+    /// Deepest collision of the collider with the walls, including walls it only touches.
+    fn wall_collision(&self, collider: &Collider) -> Option<Collision> {
+        let aabb = collider.compute_aabb();
+        let min = self.grid.world_to_grid(aabb.min);
+        let max = self.grid.world_to_grid(aabb.max);
+        (min.x..=max.x)
+            .flat_map(|x| (min.y..=max.y).map(move |y| vec2(x, y)))
+            .filter_map(|pos| self.solid_wall_at(pos))
+            .filter_map(|wall| collider.collide_within(&wall.collider, r32(CONTACT_MARGIN)))
+            .max_by_key(|collision| collision.penetration)
+    }
+
+    /// Fraction of the movement the collider can make before hitting a wall.
+    /// Walls around the depo let the train in.
+    fn sweep_walls(&self, collider: &Collider, delta: vec2<Coord>) -> Coord {
+        let aabb = collider.compute_aabb();
+        let moved = Aabb2 {
+            min: aabb.min + delta,
+            max: aabb.max + delta,
+        };
+        let min = self.grid.world_to_grid(vec2(
+            aabb.min.x.min(moved.min.x),
+            aabb.min.y.min(moved.min.y),
+        ));
+        let max = self.grid.world_to_grid(vec2(
+            aabb.max.x.max(moved.max.x),
+            aabb.max.y.max(moved.max.y),
+        ));
+        (min.x..=max.x)
+            .flat_map(|x| (min.y..=max.y).map(move |y| vec2(x, y)))
+            .filter_map(|pos| self.solid_wall_at(pos))
+            .filter(|wall| !wall.collider.check(&self.depo))
+            .filter_map(|wall| collider.cast(delta, &wall.collider))
+            .map(|hit| hit.time)
+            .min()
+            .unwrap_or(Coord::ONE)
+    }
+
+    /// Wall in the cell the train runs into.
+    /// Ghost trains phase through the interior walls but not the border.
+    fn solid_wall_at(&self, position: vec2<ICoord>) -> Option<&Wall> {
+        if self.train.ghost.is_some() && self.is_inside_map(position) {
+            return None;
+        }
+        self.grid_index.wall_at(&self.grid_items, position)
+    }
+
+    fn hits_wall(&self, collider: &Collider) -> bool {
+        // Check only the cells the collider overlaps
+        let aabb = collider.compute_aabb();
+        let min = self.grid.world_to_grid(aabb.min);
+        let max = self.grid.world_to_grid(aabb.max);
+        (min.x..=max.x)
+            .flat_map(|x| (min.y..=max.y).map(move |y| vec2(x, y)))
+            .filter_map(|pos| self.grid_index.wall_at(&self.grid_items, pos))
+            .any(|wall| collider.check(&wall.collider))
+    }
+
+    /// Whether the locomotive overlaps any of its wagons.
+    fn overlaps_wagons(&self) -> bool {
+        let Some(head) = self.train.blocks.front() else {
+            return false;
+        };
+        self.train
+            .blocks
+            .iter()
+            .skip(1)
+            .any(|block| head.collider.check(&block.collider))
+    }
+
+    fn update_recoil(&mut self, delta_time: FloatTime) {
+        let overlapping = self.overlaps_wagons();
+        if let Some(timer) = &mut self.train.recoil {
+            timer.change(-delta_time);
+            // Keep passing through until the locomotive is clear of the wagons
+            if timer.is_min() && !overlapping {
+                self.train.recoil = None;
+            }
+        }
+    }
 
-            self.context.play_sfx(&self.context.assets.sounds.puff);
+    fn update_ghost(&mut self, delta_time: FloatTime) {
+        if let Some(timer) = &mut self.train.ghost {
+            timer.change(-delta_time);
+            if timer.is_min() {
+                self.train.ghost = None;
+            }
         }
     }
 
@@ -202,7 +332,7 @@ impl Model {
         let Some(tail) = self.train.blocks.back() else {
             return;
         };
-        let mut space_left = self.config.train.wagon_spacing + self.config.train.wagon_size.x;
+        let mut space_left = self.stats.wagon_spacing + self.stats.wagon_size.x;
         let (anchor, dir) = if let Some((to, from)) = std::iter::once(tail.collider.position)
             .chain(tail.path.iter().copied())
             .tuple_windows()
@@ -217,7 +347,7 @@ impl Model {
             }) {
             (to, (from - to).normalize_or_zero())
         } else {
-            space_left = self.config.train.wagon_spacing + self.config.train.wagon_size.x;
+            space_left = self.stats.wagon_spacing + self.stats.wagon_size.x;
             (tail.collider.position, -tail.collider.rotation.unit_vec())
         };
         let position = anchor + dir * space_left;
@@ -225,7 +355,7 @@ impl Model {
         self.train.blocks.push_back(TrainBlock {
             kind,
             collider: Collider {
-                shape: Shape::rectangle(self.config.train.wagon_size),
+                shape: Shape::rectangle(self.stats.wagon_size),
                 position,
                 rotation,
             },
@@ -235,113 +365,146 @@ impl Model {
         });
     }
 
-    fn move_train(&mut self, delta_time: FloatTime, player_input: &PlayerInput) {
-        if self.train.blocks.is_empty() {
-            self.next_round();
-            return;
+    /// Move the head of the train along the rails or by the player input.
+    /// Moves in steps short enough to not skip any rail cells,
+    /// and stops at the first wall on the way.
+    /// Returns whether the wagon is on a rail.
+    fn move_head(
+        &self,
+        wagon: &mut TrainBlock,
+        speed: Coord,
+        player_input: &PlayerInput,
+        delta_time: FloatTime,
+    ) -> bool {
+        let cell = self.grid.cell_size.x.min(self.grid.cell_size.y);
+        let steps = (speed * delta_time / (cell * r32(MAX_HEAD_STEP)))
+            .ceil()
+            .as_f32()
+            .max(1.0) as usize;
+        let step_time = delta_time / r32(steps as f32);
+
+        let mut on_rail = false;
+        for _ in 0..steps {
+            on_rail = self.steer_head(wagon, speed, player_input, step_time);
+
+            // Movement
+            let delta = wagon.collider.rotation.unit_vec() * speed * step_time;
+            let time = self.sweep_walls(&wagon.collider, delta);
+            wagon.collider.position += delta * time;
+            if time < Coord::ONE {
+                // Stopped by a wall
+                break;
+            }
         }
+        on_rail
+    }
 
-        // Returns whether the wagon is on a rail
-        let move_head = |wagon: &mut TrainBlock, player_input: &PlayerInput| -> bool {
-            let move_dir = wagon.collider.rotation.unit_vec();
-            let pos = self.grid.world_to_grid(wagon.collider.position);
-            let on_rail = if let Some((_rail_pos, rail)) =
-                query!(self.grid_items, (&position, &rail.Get.Some))
-                    .find(|(&position, _)| position == pos)
-            {
-                // On a rail
-                let cons = Connections::from(rail.orientation);
-                let cons = [cons.right, cons.top, cons.left, cons.bottom];
-
-                let rail_pos = self.grid.grid_to_world(pos);
-                let offset = wagon.collider.position - rail_pos;
-
-                let face_side = (wagon
-                    .collider
-                    .rotation
-                    .normalized_2pi()
-                    .as_degrees()
-                    .as_f32()
-                    / 90.0)
-                    .round() as usize
-                    % 4;
-                let back_side = (face_side + 2) % 4;
-
-                let ninety = Angle::from_degrees(r32(90.0));
-                if cons[back_side] && vec2::dot(offset, move_dir) < Coord::ZERO {
-                    // Entering the rail
+    /// Align the head with the rail it is on, or turn it by the player input.
+    /// Returns whether the wagon is on a rail.
+    fn steer_head(
+        &self,
+        wagon: &mut TrainBlock,
+        speed: Coord,
+        player_input: &PlayerInput,
+        delta_time: FloatTime,
+    ) -> bool {
+        let move_dir = wagon.collider.rotation.unit_vec();
+        let pos = self.grid.world_to_grid(wagon.collider.position);
+        if let Some(rail) = self.grid_index.rail_at(&self.grid_items, pos) {
+            // On a rail
+            let rail_pos = self.grid.grid_to_world(pos);
+            let offset = wagon.collider.position - rail_pos;
+
+            let face_side = (wagon
+                .collider
+                .rotation
+                .normalized_2pi()
+                .as_degrees()
+                .as_f32()
+                / 90.0)
+                .round() as usize
+                % 4;
+            let back_side = (face_side + 2) % 4;
+
+            // Junctions route the train by their switch until it passes the center
+            let cons = if wagon.entering_rail {
+                rail.route(back_side)
+            } else {
+                rail.sides()
+            };
+
+            let ninety = Angle::from_degrees(r32(90.0));
+            if cons[back_side] && vec2::dot(offset, move_dir) < Coord::ZERO {
+                // Entering the rail
+                // Align train with the rail
+                wagon.collider.rotation = ninety * r32(face_side as f32);
+                let rail_dir = wagon.collider.rotation.unit_vec();
+                wagon.collider.position =
+                    rail_pos + rail_dir * vec2::dot(wagon.collider.position - rail_pos, rail_dir);
+
+                if !wagon.entering_rail {
+                    // Just entered
+                    wagon.path.push_front(wagon.collider.position);
+                }
+
+                wagon.snapped_to_rail = true;
+                wagon.entering_rail = true;
+                true
+            } else {
+                // Leaving the rail
+                let rail_dir = ninety * r32(face_side as f32);
+
+                // Crossed the center of the rail - turn
+                // Crossings are open ahead, so the train always goes straight through them
+                let on_rail = if wagon.snapped_to_rail && wagon.entering_rail && !cons[face_side] {
+                    // Find the turn
+                    if cons[(face_side + 1) % 4] {
+                        // Turn left
+                        wagon.collider.rotation = rail_dir + ninety;
+                        wagon.collider.position = rail_pos;
+                        wagon.path.push_front(rail_pos);
+                        true
+                    } else if cons[(face_side + 3) % 4] {
+                        // Turn right
+                        wagon.collider.rotation = rail_dir - ninety;
+                        wagon.collider.position = rail_pos;
+                        wagon.path.push_front(rail_pos);
+                        true
+                    } else {
+                        false
+                    }
+                } else if cons[face_side] {
                     // Align train with the rail
                     wagon.collider.rotation = ninety * r32(face_side as f32);
                     let rail_dir = wagon.collider.rotation.unit_vec();
                     wagon.collider.position = rail_pos
                         + rail_dir * vec2::dot(wagon.collider.position - rail_pos, rail_dir);
-
-                    if !wagon.entering_rail {
-                        // Just entered
+                    if wagon.entering_rail {
                         wagon.path.push_front(wagon.collider.position);
                     }
-
-                    wagon.snapped_to_rail = true;
-                    wagon.entering_rail = true;
                     true
                 } else {
-                    // Leaving the rail
-                    let rail_dir = ninety * r32(face_side as f32);
-
-                    // Crossed the center of the rail - turn
-                    let on_rail =
-                        if wagon.snapped_to_rail && wagon.entering_rail && !cons[face_side] {
-                            // Find the turn
-                            if cons[(face_side + 1) % 4] {
-                                // Turn left
-                                wagon.collider.rotation = rail_dir + ninety;
-                                wagon.collider.position = rail_pos;
-                                wagon.path.push_front(rail_pos);
-                                true
-                            } else if cons[(face_side + 3) % 4] {
-                                // Turn right
-                                wagon.collider.rotation = rail_dir - ninety;
-                                wagon.collider.position = rail_pos;
-                                wagon.path.push_front(rail_pos);
-                                true
-                            } else {
-                                false
-                            }
-                        } else if cons[face_side] {
-                            // Align train with the rail
-                            wagon.collider.rotation = ninety * r32(face_side as f32);
-                            let rail_dir = wagon.collider.rotation.unit_vec();
-                            wagon.collider.position = rail_pos
-                                + rail_dir
-                                    * vec2::dot(wagon.collider.position - rail_pos, rail_dir);
-                            if wagon.entering_rail {
-                                wagon.path.push_front(wagon.collider.position);
-                            }
-                            true
-                        } else {
-                            false
-                        };
-
-                    wagon.snapped_to_rail = on_rail;
-                    wagon.entering_rail = false;
-                    on_rail
-                }
-            } else {
-                // Turn by player input
-                wagon.collider.rotation += self.config.train.turn_speed
-                    * player_input.turn
-                    * delta_time
-                    * self.train.train_speed.min(Coord::ONE);
+                    false
+                };
 
-                false
-            };
+                wagon.snapped_to_rail = on_rail;
+                wagon.entering_rail = false;
+                on_rail
+            }
+        } else {
+            // Turn by player input
+            wagon.collider.rotation +=
+                self.stats.turn_speed * player_input.turn * delta_time * speed.min(Coord::ONE);
 
-            // Movement
-            wagon.collider.position +=
-                wagon.collider.rotation.unit_vec() * self.train.train_speed * delta_time;
+            false
+        }
+    }
 
-            on_rail
-        };
+    fn move_train(&mut self, delta_time: FloatTime, player_input: &PlayerInput) {
+        if self.train.blocks.is_empty() {
+            self.next_round();
+            return;
+        }
 
         // Returns whether the wagon is on a rail
         let move_wagon = |head: &mut TrainBlock, wagon: &mut TrainBlock| -> bool {
@@ -359,12 +522,8 @@ impl Model {
                 }
 
                 let pos = self.grid.world_to_grid(wagon.collider.position);
-                if let Some((_rail_pos, rail)) =
-                    query!(self.grid_items, (&position, &rail.Get.Some))
-                        .find(|(&position, _)| position == pos)
-                {
-                    let cons = Connections::from(rail.orientation);
-                    let cons = [cons.right, cons.top, cons.left, cons.bottom];
+                if let Some(rail) = self.grid_index.rail_at(&self.grid_items, pos) {
+                    let cons = rail.sides();
 
                     let rail_pos = self.grid.grid_to_world(pos);
                     let offset = wagon.collider.position - rail_pos;
@@ -378,7 +537,7 @@ impl Model {
                 }
             };
 
-            let mut space_left = self.config.train.wagon_spacing + self.config.train.wagon_size.x;
+            let mut space_left = self.stats.wagon_spacing + self.stats.wagon_size.x;
             if let Some((i, (to, from))) = std::iter::once(head.collider.position)
                 .chain(head.path.iter().copied())
                 .chain(std::iter::once(wagon.collider.position))
@@ -406,50 +565,38 @@ impl Model {
         };
 
         // Move wagons
+        let mut blocks = std::mem::take(&mut self.train.blocks);
         let mut on_rail = 0;
-        let mut blocks = self.train.blocks.iter_mut();
-        if let Some(mut head) = blocks.next() {
-            self.particles_queue.push(SpawnParticles {
-                kind: ParticleKind::Steam,
-                density: r32(4.0) * self.train.train_speed.clamp(r32(0.5), r32(5.0)),
-                distribution: ParticleDistribution::Circle {
-                    center: head.collider.position
-                        + head.collider.rotation.unit_vec() * self.config.train.wagon_size.x
-                            / r32(2.5),
-                    radius: r32(0.1),
-                },
-                size: r32(0.05)..=r32(0.15),
-                velocity: -head.collider.rotation.unit_vec()
-                    * (self.train.train_speed * r32(0.5)).clamp(r32(0.1), r32(0.5)),
-                ..default()
-            });
-            if move_head(head, player_input) {
+        let mut iter = blocks.iter_mut();
+        if let Some(mut head) = iter.next() {
+            if self.move_head(head, self.train.train_speed, player_input, delta_time) {
                 on_rail += 1;
             }
 
-            for block in blocks {
+            for block in iter {
                 if move_wagon(head, block) {
                     on_rail += 1;
                 }
                 head = block;
             }
         }
+        self.train.blocks = blocks;
 
         // Acceleration
-        self.train.target_speed = self.config.train.offrail_speed
-            + (self.config.train.rail_speed - self.config.train.offrail_speed)
+        self.train.target_speed = self.stats.offrail_speed
+            + (self.stats.rail_speed - self.stats.offrail_speed)
                 * r32(on_rail as f32 / self.train.blocks.len() as f32);
-        let slowdown_s = self.config.train.offrail_speed;
-        let slowdown_t = slowdown_s / self.config.train.overtime_slowdown
+        let slowdown_s = self.stats.offrail_speed;
+        let slowdown_t = slowdown_s / self.stats.overtime_slowdown
             - r32((self.train.blocks.len() as f32 - 1.0) * 2.0);
         let t = self.round_time / slowdown_t;
         let slowdown = t * t * t * slowdown_s;
         let target = (self.train.target_speed - slowdown).max(Coord::ZERO);
         let current_speed = self.train.train_speed;
         let acceleration = if target > current_speed {
-            self.config.train.acceleration
+            self.stats.acceleration
         } else {
-            -self.config.train.deceleration
+            -self.stats.deceleration
         };
         self.train.train_speed =
             current_speed + (acceleration * delta_time).clamp_abs((target - current_speed).abs());
@@ -459,25 +606,3 @@ impl Model {
         }
     }
 }
-
-fn spawn_text(text: impl Into<Name>, position: vec2<Coord>) -> FloatingText {
-    let mut rng = thread_rng();
-    let text = text.into();
-
-    let angle = Angle::from_radians(r32(rng.gen_range(1.0..=2.0)));
-    let speed = r32(rng.gen_range(0.5..=1.0));
-    let velocity = angle.unit_vec() * speed;
-
-    FloatingText {
-        position,
-        velocity,
-        size: r32(0.75),
-        color: if text.starts_with('-') {
-            Color::try_from("#ff4f69").unwrap()
-        } else {
-            Color::try_from("#fff7f8").unwrap()
-        },
-        lifetime: Bounded::new_max(r32(1.5)),
-        text,
-    }
-}
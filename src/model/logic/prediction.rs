@@ -0,0 +1,38 @@
+use super::*;
+
+impl Model {
+    /// Predict the path of the locomotive launched from the depo with no player input,
+    /// assuming it keeps the launch speed.
+    /// Stops early when the locomotive hits a wall or comes back to the depo.
+    pub fn predict_trajectory(
+        &self,
+        duration: FloatTime,
+        delta_time: FloatTime,
+    ) -> Vec<vec2<Coord>> {
+        let mut head = self.depo_locomotive();
+        let speed = self.stats.rail_speed;
+        let input = PlayerInput::default();
+
+        let mut points = vec![head.collider.position];
+        let mut left_depo = false;
+        let mut time = FloatTime::ZERO;
+        while time < duration {
+            time += delta_time;
+            self.move_head(&mut head, speed, &input, delta_time);
+            head.path.clear();
+            points.push(head.collider.position);
+
+            if head.collider.check(&self.depo) {
+                if left_depo {
+                    break;
+                }
+            } else {
+                left_depo = true;
+                if self.wall_collision(&head.collider).is_some() {
+                    break;
+                }
+            }
+        }
+        points
+    }
+}
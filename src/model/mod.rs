@@ -1,8 +1,12 @@
 mod collider;
+mod level;
 mod logic;
+mod modifier;
 mod particles;
+mod replay;
+mod save;
 
-pub use self::{collider::*, particles::*};
+pub use self::{collider::*, level::*, modifier::*, particles::*, replay::*, save::*};
 
 use crate::prelude::*;
 
@@ -29,9 +33,20 @@ pub struct Train {
     pub in_depo: bool,
     pub target_speed: Coord,
     pub train_speed: Coord,
+    /// Time left while the train phases through the interior walls.
+    pub ghost: Option<Bounded<FloatTime>>,
+    /// Time left while the locomotive passes through its own wagons after bouncing off a wall.
+    pub recoil: Option<Bounded<FloatTime>>,
     pub blocks: VecDeque<TrainBlock>,
 }
 
+/// Wagon detached from the train when it derailed.
+#[derive(Debug, Clone)]
+pub struct Debris {
+    pub collider: Collider,
+    pub velocity: vec2<Coord>,
+}
+
 #[derive(Debug, Clone)]
 pub struct TrainBlock {
     pub kind: TrainBlockKind,
@@ -69,13 +84,57 @@ pub enum TrainBlockKind {
     Wagon,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum Resource {
-    Coal,
-    Coin,
-    Diamond,
-    PlusCent,
-    GhostFuel,
+/// Gameplay events emitted by the model for audio, effects, and stats to react to.
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    TrainLaunched,
+    ResourceCollected {
+        resource: ResourceId,
+        position: vec2<Coord>,
+        score: Score,
+        money: Money,
+    },
+    WagonDestroyed {
+        position: vec2<Coord>,
+        velocity: vec2<Coord>,
+        score: Score,
+    },
+    /// The locomotive ran into a wall.
+    Derailed {
+        position: vec2<Coord>,
+        /// Velocity of the impact, along the wall normal.
+        velocity: vec2<Coord>,
+        score: Score,
+        /// Number of wagons detached as debris.
+        detached: usize,
+    },
+    RoundEnded {
+        score: Score,
+    },
+    QuotaCompleted {
+        quota: Score,
+        score: Score,
+    },
+    GameOver {
+        total_score: Score,
+    },
+    LevelCompleted {
+        total_score: Score,
+    },
+    ShopPurchase {
+        upgrade: Upgrade,
+        price: Money,
+    },
+}
+
+/// Key of a resource definition in [`Config::resources`].
+pub type ResourceId = String;
+
+/// Special effect applied when a resource is collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceEffect {
+    /// Lets the train phase through the interior walls for `ghost_duration`.
+    Ghost,
 }
 
 #[derive(geng::asset::Load, Debug, Clone, Serialize, Deserialize)]
@@ -83,9 +142,17 @@ pub enum Resource {
 pub struct Config {
     pub map_size: vec2<ICoord>,
     pub depo_size: vec2<Coord>,
+    /// Number of rail cards drawn into the hand each round.
+    pub hand_size: usize,
+    /// Number of random resources spawned each round on top of the deck, weighted by rarity.
+    pub bonus_resources: RangeInclusive<usize>,
     pub deck: Deck,
     pub train: TrainConfig,
-    pub resources: HashMap<Resource, ResourceConfig>,
+    pub quota: QuotaConfig,
+    pub shop: ShopConfig,
+    pub walls: WallsConfig,
+    pub derail: DerailConfig,
+    pub resources: HashMap<ResourceId, ResourceConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,12 +165,217 @@ pub struct TrainConfig {
     pub deceleration: Coord,
     pub wagon_size: vec2<Coord>,
     pub wagon_spacing: Coord,
+    /// How long the train phases through the interior walls
+    /// after collecting a resource with [`ResourceEffect::Ghost`].
+    pub ghost_duration: FloatTime,
+    /// Number of wagons right behind the locomotive it cannot run into.
+    pub self_collision_skip: usize,
+    /// What happens when the locomotive runs into its own wagons.
+    pub self_collision: SelfCollision,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelfCollision {
+    /// The wagons from the hit one to the end of the train are destroyed.
+    LoseTail,
+    /// The round ends immediately.
+    EndRound,
+}
+
+impl Config {
+    pub fn upgrade(&self, upgrade: &Upgrade) -> Option<&UpgradeConfig> {
+        self.shop
+            .upgrades
+            .iter()
+            .find(|config| config.upgrade == *upgrade)
+    }
+
+    /// Check the settings that would otherwise fail in the middle of a run.
+    pub fn validate(&self) -> Result<()> {
+        check_range("bonus_resources", &self.bonus_resources)?;
+        check_range("quota.growth_noise", &self.quota.growth_noise)?;
+        check_range("quota.round_noise", &self.quota.round_noise)?;
+        check_range("quota.round_bonus", &self.quota.round_bonus)?;
+        check_range("walls.corridor_length", &self.walls.corridor_length)?;
+        check_range("walls.room_size", &self.walls.room_size)?;
+        for (id, resource) in &self.resources {
+            if let Some(payout) = &resource.payout {
+                check_range(&format!("resources.{}.payout", id), payout)?;
+            }
+            anyhow::ensure!(
+                Color::try_from(resource.color.as_str()).is_ok(),
+                "resources.{}.color {:?} is not a hex color",
+                id,
+                resource.color
+            );
+        }
+        for id in &self.deck.resources {
+            self.check_resource("deck.resources", id)?;
+        }
+        for config in &self.shop.upgrades {
+            if let Upgrade::Resource(id) = &config.upgrade {
+                self.check_resource("shop.upgrades", id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the resource is defined in [`Config::resources`].
+    pub fn check_resource(&self, name: &str, id: &ResourceId) -> Result<()> {
+        anyhow::ensure!(
+            self.resources.contains_key(id),
+            "{} uses resource {:?} that is not defined in resources",
+            name,
+            id
+        );
+        Ok(())
+    }
+}
+
+fn check_range<T: PartialOrd + Debug>(name: &str, range: &RangeInclusive<T>) -> Result<()> {
+    anyhow::ensure!(
+        range.start() <= range.end(),
+        "{} is empty: start {:?} is after end {:?}",
+        name,
+        range.start(),
+        range.end()
+    );
+    Ok(())
+}
+
+/// Quota progression and round payouts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Score required by the first quota.
+    pub first: Score,
+    /// Number of days given to complete a quota.
+    pub days: usize,
+    /// Quota increase after completing the `n`-th quota is `growth * n ^ growth_power * noise`.
+    pub growth: R32,
+    pub growth_power: R32,
+    pub growth_noise: RangeInclusive<R32>,
+    /// Fraction of the quota score paid out when the quota is completed.
+    pub quota_payout: R32,
+    /// Money for a round is `round_score * round_payout * round_noise + round_bonus`.
+    pub round_payout: R32,
+    pub round_noise: RangeInclusive<R32>,
+    pub round_bonus: RangeInclusive<R32>,
+}
+
+/// Upgrades offered in the shop between rounds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShopConfig {
+    /// Number of upgrades offered each round.
+    pub slots: usize,
+    /// One random offer each round gets a discount.
+    pub discounts: Vec<ShopDiscount>,
+    pub upgrades: Vec<UpgradeConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShopDiscount {
+    /// Fraction of the price taken off.
+    pub discount: R32,
+    pub weight: R32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpgradeConfig {
+    pub upgrade: Upgrade,
+    pub price: Money,
+    /// Relative chance to be offered.
+    pub weight: R32,
+    /// Train stat changes granted by the upgrade.
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    /// How many times the upgrade can be bought, unlimited if not set.
+    #[serde(default)]
+    pub max_stacks: Option<usize>,
+    /// Tooltip shown in the shop.
+    pub description: String,
+}
+
+/// Response of the train to running into a wall.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerailConfig {
+    /// Fraction of the impact speed the locomotive bounces back with on a penalized impact,
+    /// zero slides along the wall.
+    pub bounce: R32,
+    /// Impact speed below which the locomotive slides along the wall without a penalty.
+    pub min_impact: Coord,
+    /// Fraction of the speed lost on a penalized impact.
+    pub speed_loss: R32,
+    /// Fraction of the round score lost per unit of impact speed above `min_impact`.
+    pub penalty: R32,
+    /// Maximum fraction of the round score lost in a single impact.
+    pub max_penalty: R32,
+    /// Impact speed above which wagons get detached from the end of the train.
+    pub detach_speed: Coord,
+    /// Number of wagons detached per unit of impact speed above `detach_speed`.
+    pub detach_per_speed: R32,
+    /// Deceleration of the detached wagons.
+    pub debris_friction: Coord,
+    /// Time after a bounce during which the locomotive passes through its own wagons,
+    /// extended for as long as it still overlaps them.
+    pub recoil: FloatTime,
+}
+
+/// Interior walls generated each round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WallsConfig {
+    /// Number of single cell pillars.
+    pub pillars: usize,
+    /// Number of corridors, each made of two parallel walls.
+    pub corridors: usize,
+    pub corridor_length: RangeInclusive<ICoord>,
+    /// Free space between the walls of a corridor.
+    pub corridor_width: ICoord,
+    /// Number of rooms, walled rectangles with a doorway.
+    pub rooms: usize,
+    pub room_size: RangeInclusive<ICoord>,
+    /// Width of the room doorway.
+    pub doorway_width: ICoord,
+    /// Relative increase of the number of features with every completed quota.
+    pub growth_per_quota: R32,
+    /// Maximum fraction of the map covered by interior walls.
+    pub max_coverage: R32,
+    /// Minimum fraction of the map that has to stay free and reachable from the depo.
+    pub min_reachable: R32,
+    /// Number of cells in front of the depo kept free.
+    pub depo_clearance: ICoord,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceConfig {
-    pub value: Money,
+    /// Path to the sprite, relative to the assets directory.
+    pub sprite: String,
+    /// Name of the sprite in the UI atlas used for the shop icon.
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Hex color of the particles spawned on collection.
+    pub color: String,
+    /// Score gained when collected.
+    pub value: Score,
+    /// Spawn chance of bonus resources is inversely proportional to the rarity.
+    /// Resources with zero rarity are never spawned as a bonus.
     pub rarity: R32,
+    /// Money paid out when collected.
+    #[serde(default)]
+    pub payout: Option<RangeInclusive<Money>>,
+    /// Fraction of the round score gained on top of the value.
+    #[serde(default)]
+    pub score_share: Option<R32>,
+    /// Fraction of the current money paid out on top of the payout.
+    #[serde(default)]
+    pub money_share: Option<R32>,
+    #[serde(default)]
+    pub effect: Option<ResourceEffect>,
+}
+
+impl ResourceConfig {
+    pub fn color(&self) -> Color {
+        Color::try_from(self.color.as_str()).unwrap_or(Color::WHITE)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -127,9 +399,110 @@ impl Grid {
     }
 }
 
-#[derive(Debug, Clone)]
+pub type GridItemId = stecs::storage::arena::Index;
+
+/// Lookup of grid items by their cell.
+/// Kept in sync by [`Model::insert_grid_item`] and [`Model::remove_grid_item`].
+#[derive(Debug, Clone, Default)]
+pub struct GridIndex {
+    cells: HashMap<vec2<ICoord>, GridItemId>,
+}
+
+impl GridIndex {
+    pub fn item_at(&self, position: vec2<ICoord>) -> Option<GridItemId> {
+        self.cells.get(&position).copied()
+    }
+
+    pub fn rail_at<'a>(
+        &self,
+        items: &'a StructOf<Arena<GridItem>>,
+        position: vec2<ICoord>,
+    ) -> Option<&'a Rail> {
+        let id = self.item_at(position)?;
+        get!(items, id, (&rail.Get.Some))
+    }
+
+    pub fn resource_at<'a>(
+        &self,
+        items: &'a StructOf<Arena<GridItem>>,
+        position: vec2<ICoord>,
+    ) -> Option<&'a ResourceId> {
+        let id = self.item_at(position)?;
+        get!(items, id, (&resource.Get.Some))
+    }
+
+    pub fn wall_at<'a>(
+        &self,
+        items: &'a StructOf<Arena<GridItem>>,
+        position: vec2<ICoord>,
+    ) -> Option<&'a Wall> {
+        let id = self.item_at(position)?;
+        get!(items, id, (&wall.Get.Some))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rail {
     pub orientation: RailOrientation,
+    /// Whether a junction leads into its branch instead of the main line.
+    #[serde(default)]
+    pub switched: bool,
+    /// Whether the rail was placed by the player and can be edited during setup.
+    #[serde(default)]
+    pub player_placed: bool,
+}
+
+impl Rail {
+    pub fn new(orientation: RailOrientation) -> Self {
+        Self {
+            orientation,
+            switched: false,
+            player_placed: false,
+        }
+    }
+
+    /// Open sides of the rail, indexed as `[right, top, left, bottom]`.
+    pub fn sides(&self) -> [bool; 4] {
+        let cons = Connections::from(self.orientation);
+        [cons.right, cons.top, cons.left, cons.bottom]
+    }
+
+    /// Side of a junction that has no straight continuation, indexed as `[right, top, left, bottom]`.
+    pub fn branch_side(&self) -> Option<usize> {
+        if self.orientation.kind != RailKind::Junction {
+            return None;
+        }
+        let cons = self.sides();
+        (0..4).find(|&side| cons[side] && !cons[(side + 2) % 4])
+    }
+
+    /// Open sides for a train entering from `back_side`, indexed as `[right, top, left, bottom]`.
+    /// Junctions only leave the exit selected by the switch open.
+    pub fn route(&self, back_side: usize) -> [bool; 4] {
+        let cons = self.sides();
+        if self.orientation.kind != RailKind::Junction || !cons[back_side] {
+            return cons;
+        }
+
+        // Straight, then left, then right
+        let face_side = (back_side + 2) % 4;
+        let exits: Vec<usize> = [face_side, (face_side + 1) % 4, (face_side + 3) % 4]
+            .into_iter()
+            .filter(|&side| cons[side])
+            .collect();
+        let exit = if self.switched {
+            exits.get(1).or(exits.first())
+        } else {
+            exits.first()
+        };
+
+        let mut route = [false; 4];
+        route[back_side] = true;
+        if let Some(&exit) = exit {
+            route[exit] = true;
+        }
+        route
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -140,7 +513,7 @@ pub struct Connections {
     pub top: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct RailOrientation {
     pub kind: RailKind,
     pub rotation: usize,
@@ -150,6 +523,10 @@ pub struct RailOrientation {
 pub enum RailKind {
     Straight,
     Left,
+    /// Three-way rail with a switchable branch.
+    Junction,
+    /// Four-way crossing, passed straight through in either axis.
+    Cross,
 }
 
 impl From<RailOrientation> for Connections {
@@ -157,6 +534,8 @@ impl From<RailOrientation> for Connections {
         let mut cons = match value.kind {
             RailKind::Straight => [true, false, true, false],
             RailKind::Left => [true, false, false, true],
+            RailKind::Junction => [true, false, true, true],
+            RailKind::Cross => [true, true, true, true],
         };
         let rotation = value.rotation % cons.len();
         cons.rotate_right(rotation);
@@ -170,16 +549,16 @@ impl From<RailOrientation> for Connections {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Wall {
     pub collider: Collider,
 }
 
-#[derive(SplitFields, Debug, Clone)]
+#[derive(SplitFields, Debug, Clone, Serialize, Deserialize)]
 pub struct GridItem {
     pub position: vec2<ICoord>,
     pub rail: Option<Rail>,
-    pub resource: Option<Resource>,
+    pub resource: Option<ResourceId>,
     pub wall: Option<Wall>,
 }
 
@@ -187,23 +566,56 @@ pub struct GridItem {
 pub enum Phase {
     Setup,
     Resolution,
+    /// The quota was failed, the run is over.
+    GameOver,
+    /// The goal of the level was reached.
+    LevelComplete,
+}
+
+impl Phase {
+    /// Whether the run has ended.
+    pub fn is_over(&self) -> bool {
+        matches!(self, Self::GameOver | Self::LevelComplete)
+    }
+}
+
+/// Reversible edit of the player's rails during [`Phase::Setup`].
+#[derive(Debug, Clone)]
+pub enum SetupEdit {
+    Place {
+        position: vec2<ICoord>,
+        orientation: RailOrientation,
+    },
+    Remove {
+        position: vec2<ICoord>,
+        orientation: RailOrientation,
+    },
+    Rotate {
+        position: vec2<ICoord>,
+        from: usize,
+        to: usize,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Deck {
-    pub resources: Vec<Resource>,
+    pub resources: Vec<ResourceId>,
     pub rails: Vec<RailKind>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Upgrade {
-    Resource(Resource),
+    /// Adds the resource to the deck.
+    Resource(ResourceId),
+    /// Makes the train faster.
     Speed,
+    /// Reduces the overtime slowdown.
     Feather,
+    /// Makes the train turn faster.
     Turning,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShopItem {
     pub upgrade: Upgrade,
     pub price: Money,
@@ -211,9 +623,15 @@ pub struct ShopItem {
 }
 
 pub struct Model {
-    pub context: Context,
     pub config: Config,
 
+    /// Seed the run was started with.
+    pub seed: u64,
+    /// Source of randomness for all gameplay rolls, seeded from [`Model::seed`].
+    pub rng: StdRng,
+    /// Handcrafted board played instead of the generated one.
+    pub level: Option<Level>,
+
     pub camera: Camera2d,
     pub grid: Grid,
 
@@ -225,24 +643,56 @@ pub struct Model {
     pub current_quota: Score,
     pub quota_score: Score,
     pub quota_day: usize,
+    pub days_survived: usize,
     pub round_score: Score,
     pub money: Money,
 
     pub phase: Phase,
     pub deck: Deck,
+    /// Upgrades bought during the run.
+    pub upgrades: Vec<Upgrade>,
+    /// Train stat changes active in the run.
+    pub modifiers: Vec<Modifier>,
+    /// Train stats with the modifiers applied, see [`Model::update_stats`].
+    pub stats: TrainConfig,
+    /// Rail cards that can be placed this round.
+    pub hand: Vec<RailKind>,
+    /// Rail cards left to draw, reshuffled from the discard pile once empty.
+    pub draw_pile: Vec<RailKind>,
+    /// Placed and unplaced rail cards from the previous rounds.
+    pub discard_pile: Vec<RailKind>,
+    /// Edits made this setup phase, most recent last.
+    pub undo_stack: Vec<SetupEdit>,
+    /// Undone edits that can be redone, most recently undone last.
+    pub redo_stack: Vec<SetupEdit>,
     pub train: Train,
+    /// Wagons lost in crashes this round.
+    pub debris: Vec<Debris>,
     pub depo: Collider,
     pub shop: Vec<ShopItem>,
 
+    /// Modify through [`Model::insert_grid_item`] and [`Model::remove_grid_item`].
     pub grid_items: StructOf<Arena<GridItem>>,
-    pub particles_queue: Vec<SpawnParticles>,
-    pub particles: StructOf<Arena<Particle>>,
-    pub floating_texts: StructOf<Arena<FloatingText>>,
+    pub grid_index: GridIndex,
+    /// Events emitted since the queue was last drained.
+    pub events: Vec<GameEvent>,
 }
 
 impl Model {
-    pub fn new(context: Context, config: Config) -> Self {
+    pub fn new(config: Config, seed: u64) -> Self {
+        Self::with_level(config, seed, None)
+    }
+
+    pub fn with_level(mut config: Config, seed: u64, level: Option<Level>) -> Self {
+        log::info!("Starting a run with seed {}", seed);
+        if let Some(level) = &level {
+            config.map_size = level.map_size;
+        }
         let mut model = Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            level,
+
             camera: Camera2d {
                 center: vec2::ZERO,
                 rotation: Angle::ZERO,
@@ -261,29 +711,70 @@ impl Model {
             current_quota: 0,
             quota_score: 0,
             quota_day: 0,
+            days_survived: 0,
             round_score: 0,
             money: 0,
 
             phase: Phase::Setup,
             deck: config.deck.clone(),
+            upgrades: Vec::new(),
+            modifiers: Vec::new(),
+            stats: config.train.clone(),
+            hand: Vec::new(),
+            draw_pile: Vec::new(),
+            discard_pile: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
             train: Train {
                 in_depo: false,
                 target_speed: r32(0.0),
                 train_speed: r32(0.0),
+                ghost: None,
+                recoil: None,
                 blocks: vec![].into(),
             },
+            debris: Vec::new(),
             depo: Collider::aabb(Aabb2::ZERO),
             shop: Vec::new(),
 
             grid_items: default(),
-            particles_queue: Vec::new(),
-            particles: default(),
-            floating_texts: default(),
+            grid_index: default(),
+            events: Vec::new(),
 
-            context,
             config,
         };
         model.init();
         model
     }
+
+    pub fn item_at(&self, position: vec2<ICoord>) -> Option<GridItemId> {
+        self.grid_index.item_at(position)
+    }
+
+    /// Whether the cell is inside the map, excluding the border walls.
+    pub fn is_inside_map(&self, position: vec2<ICoord>) -> bool {
+        let size = self.config.map_size;
+        (1..=size.x).contains(&position.x) && (1..=size.y).contains(&position.y)
+    }
+
+    pub fn rail_at(&self, position: vec2<ICoord>) -> Option<&Rail> {
+        self.grid_index.rail_at(&self.grid_items, position)
+    }
+
+    pub fn insert_grid_item(&mut self, item: GridItem) -> GridItemId {
+        let position = item.position;
+        let id = self.grid_items.insert(item);
+        if self.grid_index.cells.insert(position, id).is_some() {
+            log::warn!("Multiple grid items placed at {:?}", position);
+        }
+        id
+    }
+
+    pub fn remove_grid_item(&mut self, id: GridItemId) -> Option<GridItem> {
+        let item = self.grid_items.remove(id)?;
+        if self.grid_index.item_at(item.position) == Some(id) {
+            self.grid_index.cells.remove(&item.position);
+        }
+        Some(item)
+    }
 }
@@ -0,0 +1,109 @@
+use super::*;
+
+/// Stat of the [`TrainConfig`] a [`Modifier`] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrainStat {
+    OvertimeSlowdown,
+    /// Turn speed in radians per second.
+    TurnSpeed,
+    RailSpeed,
+    OffrailSpeed,
+    Acceleration,
+    Deceleration,
+    GhostDuration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ModifierOp {
+    Add(R32),
+    Multiply(R32),
+}
+
+/// Change of a train stat on top of the base config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Modifier {
+    pub stat: TrainStat,
+    pub op: ModifierOp,
+    /// Bound the modifier cannot push the stat past.
+    /// Applies from above when the modifier increases the stat, and from below otherwise.
+    #[serde(default)]
+    pub cap: Option<R32>,
+}
+
+impl TrainConfig {
+    pub fn stat(&self, stat: TrainStat) -> R32 {
+        match stat {
+            TrainStat::OvertimeSlowdown => self.overtime_slowdown,
+            TrainStat::TurnSpeed => self.turn_speed.as_radians(),
+            TrainStat::RailSpeed => self.rail_speed,
+            TrainStat::OffrailSpeed => self.offrail_speed,
+            TrainStat::Acceleration => self.acceleration,
+            TrainStat::Deceleration => self.deceleration,
+            TrainStat::GhostDuration => self.ghost_duration,
+        }
+    }
+
+    pub fn set_stat(&mut self, stat: TrainStat, value: R32) {
+        match stat {
+            TrainStat::OvertimeSlowdown => self.overtime_slowdown = value,
+            TrainStat::TurnSpeed => self.turn_speed = Angle::from_radians(value),
+            TrainStat::RailSpeed => self.rail_speed = value,
+            TrainStat::OffrailSpeed => self.offrail_speed = value,
+            TrainStat::Acceleration => self.acceleration = value,
+            TrainStat::Deceleration => self.deceleration = value,
+            TrainStat::GhostDuration => self.ghost_duration = value,
+        }
+    }
+
+    /// Stats with the modifiers applied.
+    /// Additive modifiers go first, then the multiplicative ones, each in the order given.
+    /// Caps are enforced last, once every modifier has been applied.
+    pub fn with_modifiers<'a>(&self, modifiers: impl IntoIterator<Item = &'a Modifier>) -> Self {
+        let (additive, multiplicative): (Vec<&Modifier>, Vec<&Modifier>) = modifiers
+            .into_iter()
+            .partition(|modifier| matches!(modifier.op, ModifierOp::Add(_)));
+
+        let mut stats = self.clone();
+        for modifier in additive.iter().chain(&multiplicative) {
+            let value = stats.stat(modifier.stat);
+            let value = match modifier.op {
+                ModifierOp::Add(delta) => value + delta,
+                ModifierOp::Multiply(factor) => value * factor,
+            };
+            stats.set_stat(modifier.stat, value);
+        }
+
+        // Caps never pull a stat back past its base value
+        for modifier in additive.iter().chain(&multiplicative) {
+            let Some(cap) = modifier.cap else {
+                continue;
+            };
+            let base = self.stat(modifier.stat);
+            let value = stats.stat(modifier.stat);
+            let value = if modifier.increases() {
+                value.min(cap.max(base))
+            } else {
+                value.max(cap.min(base))
+            };
+            stats.set_stat(modifier.stat, value);
+        }
+        stats
+    }
+}
+
+impl Modifier {
+    /// Whether the modifier pushes the stat up.
+    pub fn increases(&self) -> bool {
+        match self.op {
+            ModifierOp::Add(delta) => delta > R32::ZERO,
+            ModifierOp::Multiply(factor) => factor > R32::ONE,
+        }
+    }
+}
+
+impl Model {
+    /// Recompute the effective train stats from the base config and the modifiers.
+    pub fn update_stats(&mut self) {
+        self.stats = self.config.train.with_modifiers(&self.modifiers);
+    }
+}
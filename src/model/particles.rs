@@ -34,7 +34,8 @@ pub enum ParticleKind {
     Steam,
     Wall,
     WagonDestroyed,
-    Collect(Resource),
+    /// Collected resource, colored after its definition.
+    Collect(Color),
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,174 @@
+use super::*;
+
+/// Player inputs of a run, enough to reproduce it exactly given the same config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    /// Hash of the [`Config`] the run was recorded with, see [`config_hash`].
+    pub config_hash: u64,
+    /// Level the run was played on.
+    #[serde(default)]
+    pub level: Option<Level>,
+    /// Number of fixed ticks recorded.
+    pub ticks: usize,
+    /// Player turn input of the fixed ticks, merged into runs of the same input.
+    pub turns: Vec<TurnRun>,
+    /// Actions in the order they were performed.
+    pub actions: Vec<ReplayAction>,
+}
+
+/// Turn input held for a number of consecutive fixed ticks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TurnRun {
+    pub turn: f32,
+    pub ticks: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayAction {
+    /// Index of the tick the action was performed before.
+    pub tick: usize,
+    pub action: PlayerAction,
+}
+
+/// Actions that change the model outside of the regular update.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PlayerAction {
+    LaunchTrain,
+    BuyShop(usize),
+    PlaceRail {
+        position: vec2<ICoord>,
+        orientation: RailOrientation,
+    },
+    RemoveRail(vec2<ICoord>),
+    RotateRail(vec2<ICoord>),
+    Undo,
+    Redo,
+    ToggleSwitch(vec2<ICoord>),
+    ToggleAllSwitches,
+    /// Debug cheat that drops a resource into an empty cell.
+    SpawnResource {
+        position: vec2<ICoord>,
+        resource: ResourceId,
+    },
+}
+
+impl Replay {
+    pub fn new(seed: u64, config: &Config, level: Option<Level>) -> Self {
+        Self {
+            seed,
+            config_hash: config_hash(config),
+            level,
+            ticks: 0,
+            turns: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("when reading replay {:?}", path))?;
+        let replay = ron::from_str(&data).context("when parsing the replay")?;
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let data = ron::to_string(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn record_action(&mut self, action: PlayerAction) {
+        self.actions.push(ReplayAction {
+            tick: self.ticks,
+            action,
+        });
+    }
+
+    pub fn record_tick(&mut self, input: &PlayerInput) {
+        let turn = input.turn.as_f32();
+        self.ticks += 1;
+        match self.turns.last_mut() {
+            Some(run) if run.turn == turn => run.ticks += 1,
+            _ => self.turns.push(TurnRun { turn, ticks: 1 }),
+        }
+    }
+
+    /// Play the whole replay on a fresh model.
+    pub fn simulate(&self, config: Config, delta_time: FloatTime) -> Model {
+        if config_hash(&config) != self.config_hash {
+            log::warn!("Replay was recorded with a different config");
+        }
+        let mut model = Model::with_level(config, self.seed, self.level.clone());
+        let mut player = ReplayPlayer::new(self.clone());
+        while let Some(input) = player.next_tick(&mut model) {
+            model.update(delta_time, input);
+            model.events.clear();
+        }
+        model
+    }
+}
+
+/// Feeds a [`Replay`] into a model tick by tick.
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    tick: usize,
+    /// Index of the current turn run and the ticks already played from it.
+    run: usize,
+    run_tick: usize,
+    next_action: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            tick: 0,
+            run: 0,
+            run_tick: 0,
+            next_action: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.ticks
+    }
+
+    /// Apply the actions recorded before the next tick and return its input.
+    /// Returns `None` when the replay is over.
+    pub fn next_tick(&mut self, model: &mut Model) -> Option<PlayerInput> {
+        while let Some(action) = self.replay.actions.get(self.next_action) {
+            if action.tick > self.tick {
+                break;
+            }
+            model.apply_action(action.action.clone());
+            self.next_action += 1;
+        }
+
+        let run = *self.replay.turns.get(self.run)?;
+        self.run_tick += 1;
+        if self.run_tick >= run.ticks {
+            self.run += 1;
+            self.run_tick = 0;
+        }
+        self.tick += 1;
+        Some(PlayerInput {
+            turn: r32(run.turn),
+        })
+    }
+}
+
+/// Hash of the config used to detect replays recorded with different settings.
+/// FNV-1a over the RON of the config, so it stays the same across toolchains and platforms.
+pub fn config_hash(config: &Config) -> u64 {
+    let mut config = config.clone();
+    let resources: std::collections::BTreeMap<ResourceId, ResourceConfig> =
+        std::mem::take(&mut config.resources).into_iter().collect();
+    let data = ron::to_string(&(config, resources)).expect("the config should serialize");
+
+    data.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,108 @@
+use super::*;
+
+/// State of a run between rounds, enough to resume it later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSave {
+    pub seed: u64,
+    /// Seed for the rng of the resumed run.
+    pub rng_seed: u64,
+    #[serde(default)]
+    pub level: Option<Level>,
+
+    pub quotas_completed: usize,
+    pub total_score: Score,
+    pub current_quota: Score,
+    pub quota_score: Score,
+    pub quota_day: usize,
+    pub days_survived: usize,
+    pub money: Money,
+
+    pub deck: Deck,
+    #[serde(default)]
+    pub upgrades: Vec<Upgrade>,
+    pub hand: Vec<RailKind>,
+    pub draw_pile: Vec<RailKind>,
+    pub discard_pile: Vec<RailKind>,
+    pub modifiers: Vec<Modifier>,
+    pub shop: Vec<ShopItem>,
+    pub depo: Collider,
+    pub grid_items: Vec<GridItem>,
+}
+
+impl Model {
+    /// Save the run state.
+    /// Only possible during [`Phase::Setup`], returns `None` otherwise.
+    pub fn save(&self) -> Option<RunSave> {
+        let Phase::Setup = self.phase else {
+            return None;
+        };
+
+        let grid_items = query!(self.grid_items, (&position, &rail, &resource, &wall))
+            .map(|(&position, rail, resource, wall)| GridItem {
+                position,
+                rail: rail.clone(),
+                resource: resource.clone(),
+                wall: wall.clone(),
+            })
+            .collect();
+
+        Some(RunSave {
+            seed: self.seed,
+            // Clone to keep the current run unaffected by saving
+            rng_seed: self.rng.clone().gen(),
+            level: self.level.clone(),
+
+            quotas_completed: self.quotas_completed,
+            total_score: self.total_score,
+            current_quota: self.current_quota,
+            quota_score: self.quota_score,
+            quota_day: self.quota_day,
+            days_survived: self.days_survived,
+            money: self.money,
+
+            deck: self.deck.clone(),
+            upgrades: self.upgrades.clone(),
+            hand: self.hand.clone(),
+            draw_pile: self.draw_pile.clone(),
+            discard_pile: self.discard_pile.clone(),
+            modifiers: self.modifiers.clone(),
+            shop: self.shop.clone(),
+            depo: self.depo.clone(),
+            grid_items,
+        })
+    }
+
+    /// Resume a saved run.
+    pub fn load(config: Config, save: RunSave) -> Self {
+        let mut model = Self::with_level(config, save.seed, save.level);
+        model.rng = StdRng::seed_from_u64(save.rng_seed);
+
+        model.quotas_completed = save.quotas_completed;
+        model.total_score = save.total_score;
+        model.current_quota = save.current_quota;
+        model.quota_score = save.quota_score;
+        model.quota_day = save.quota_day;
+        model.days_survived = save.days_survived;
+        model.money = save.money;
+
+        model.deck = save.deck;
+        model.upgrades = save.upgrades;
+        model.modifiers = save.modifiers;
+        model.update_stats();
+        model.hand = save.hand;
+        model.draw_pile = save.draw_pile;
+        model.discard_pile = save.discard_pile;
+        model.shop = save.shop;
+        model.depo = save.depo;
+
+        model.grid_items = default();
+        model.grid_index = default();
+        for item in save.grid_items {
+            model.insert_grid_item(item);
+        }
+
+        model.reset_train();
+        model.phase = Phase::Setup;
+        model
+    }
+}
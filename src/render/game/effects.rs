@@ -0,0 +1,194 @@
+use super::*;
+
+/// Visual effects driven by the model state and its events.
+pub struct GameEffects {
+    pub particles_queue: Vec<SpawnParticles>,
+    pub particles: StructOf<Arena<Particle>>,
+    pub floating_texts: StructOf<Arena<FloatingText>>,
+}
+
+impl GameEffects {
+    pub fn new() -> Self {
+        Self {
+            particles_queue: Vec::new(),
+            particles: default(),
+            floating_texts: default(),
+        }
+    }
+
+    pub fn handle_event(&mut self, config: &Config, event: &GameEvent) {
+        match *event {
+            GameEvent::ResourceCollected {
+                ref resource,
+                position,
+                score,
+                ..
+            } => {
+                let color = config
+                    .resources
+                    .get(resource)
+                    .map_or(Color::WHITE, |resource| resource.color());
+                self.particles_queue.push(SpawnParticles {
+                    kind: ParticleKind::Collect(color),
+                    density: r32(10.0),
+                    distribution: ParticleDistribution::Circle {
+                        center: position,
+                        radius: r32(0.5),
+                    },
+                    velocity: vec2(0.0, 1.0).as_r32(),
+                    ..default()
+                });
+                self.spawn_score(score, position);
+            }
+            GameEvent::WagonDestroyed {
+                position,
+                velocity,
+                score,
+            } => {
+                let speed = velocity.len();
+                self.particles_queue.push(SpawnParticles {
+                    kind: ParticleKind::WagonDestroyed,
+                    density: r32(20.0),
+                    distribution: ParticleDistribution::Circle {
+                        center: position,
+                        radius: r32(0.5),
+                    },
+                    size: r32(0.1)..=r32(0.15),
+                    velocity: -velocity.normalize_or_zero()
+                        * (speed * r32(0.5)).clamp(r32(0.5), r32(1.0)),
+                    ..default()
+                });
+                self.spawn_score(score, position);
+            }
+            GameEvent::Derailed {
+                position,
+                velocity,
+                score,
+                ..
+            } => {
+                let speed = velocity.len();
+                self.particles_queue.push(SpawnParticles {
+                    kind: ParticleKind::Wall,
+                    density: r32(5.0) * speed.clamp(r32(1.0), r32(4.0)),
+                    distribution: ParticleDistribution::Circle {
+                        center: position,
+                        radius: r32(0.3),
+                    },
+                    size: r32(0.05)..=r32(0.1),
+                    velocity: -velocity.normalize_or_zero() * r32(0.5),
+                    ..default()
+                });
+                self.spawn_score(score, position);
+            }
+            _ => {}
+        }
+    }
+
+    fn spawn_score(&mut self, score: Score, position: vec2<Coord>) {
+        if score != 0 {
+            self.floating_texts
+                .insert(spawn_text(format!("{:+}", score), position));
+        }
+    }
+
+    pub fn update(&mut self, model: &Model, delta_time: FloatTime) {
+        self.passive_particles(model, delta_time);
+        self.process_particles(delta_time);
+    }
+
+    fn passive_particles(&mut self, model: &Model, _delta_time: FloatTime) {
+        for wall in query!(model.grid_items, (&wall.Get.Some)) {
+            if wall.collider.check(&model.depo) {
+                continue;
+            }
+            self.particles_queue.push(SpawnParticles {
+                kind: ParticleKind::Wall,
+                density: r32(0.5),
+                distribution: ParticleDistribution::Aabb(wall.collider.compute_aabb()),
+                size: r32(0.05)..=r32(0.1),
+                ..default()
+            });
+        }
+
+        // Steam
+        if let Phase::Resolution = model.phase {
+            if let Some(head) = model.train.blocks.front() {
+                let speed = model.train.train_speed;
+                self.particles_queue.push(SpawnParticles {
+                    kind: ParticleKind::Steam,
+                    density: r32(4.0) * speed.clamp(r32(0.5), r32(5.0)),
+                    distribution: ParticleDistribution::Circle {
+                        center: head.collider.position
+                            + head.collider.rotation.unit_vec() * model.stats.wagon_size.x
+                                / r32(2.5),
+                        radius: r32(0.1),
+                    },
+                    size: r32(0.05)..=r32(0.15),
+                    velocity: -head.collider.rotation.unit_vec()
+                        * (speed * r32(0.5)).clamp(r32(0.1), r32(0.5)),
+                    ..default()
+                });
+            }
+        }
+    }
+
+    fn process_particles(&mut self, delta_time: FloatTime) {
+        // Floating texts
+        let mut dead_ids = Vec::new();
+        for (id, position, velocity, lifetime) in query!(
+            self.floating_texts,
+            (id, &mut position, &velocity, &mut lifetime)
+        ) {
+            *position += *velocity * delta_time;
+            lifetime.change(-delta_time);
+            if lifetime.is_min() {
+                dead_ids.push(id);
+            }
+        }
+        for id in dead_ids {
+            self.floating_texts.remove(id);
+        }
+
+        // Particles
+        let mut dead_ids = Vec::new();
+        for (id, position, velocity, lifetime) in query!(
+            self.particles,
+            (id, &mut position, &velocity, &mut lifetime)
+        ) {
+            *position += *velocity * delta_time;
+            lifetime.change(-delta_time);
+            if lifetime.is_min() {
+                dead_ids.push(id);
+            }
+        }
+        for id in dead_ids {
+            self.particles.remove(id);
+        }
+        let spawn = self.particles_queue.drain(..).flat_map(spawn_particles);
+        for particle in spawn {
+            self.particles.insert(particle);
+        }
+    }
+}
+
+fn spawn_text(text: impl Into<Name>, position: vec2<Coord>) -> FloatingText {
+    let mut rng = thread_rng();
+    let text = text.into();
+
+    let angle = Angle::from_radians(r32(rng.gen_range(1.0..=2.0)));
+    let speed = r32(rng.gen_range(0.5..=1.0));
+    let velocity = angle.unit_vec() * speed;
+
+    FloatingText {
+        position,
+        velocity,
+        size: r32(0.75),
+        color: if text.starts_with('-') {
+            Color::try_from("#ff4f69").unwrap()
+        } else {
+            Color::try_from("#fff7f8").unwrap()
+        },
+        lifetime: Bounded::new_max(r32(1.5)),
+        text,
+    }
+}
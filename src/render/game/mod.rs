@@ -1,10 +1,15 @@
+mod effects;
 mod ui;
 
+pub use self::effects::*;
+
 use super::{mask::MaskedStack, util::*};
 
 use crate::{model::*, prelude::*};
 
 const OUTLINE_WIDTH: f32 = 0.1;
+/// How far ahead the train trajectory is predicted during setup, in seconds.
+const PREDICTION_TIME: f32 = 3.0;
 
 #[derive(Debug, Clone)]
 pub struct GameRenderOptions {
@@ -22,6 +27,7 @@ impl Default for GameRenderOptions {
 
 pub struct GameRender {
     context: Context,
+    pub effects: GameEffects,
     util: UtilRender,
     mask_stack: MaskedStack,
     ui_texture: ugli::Texture,
@@ -38,10 +44,54 @@ impl GameRender {
             mask_stack: MaskedStack::new(&context.geng, &context.assets),
             ui_texture,
             ui_depth,
+            effects: GameEffects::new(),
             context,
         }
     }
 
+    fn rail_texture(&self, kind: RailKind) -> &ugli::Texture {
+        let sprites = &self.context.assets.sprites;
+        match kind {
+            RailKind::Straight => &sprites.rail_straight,
+            RailKind::Left => &sprites.rail_left,
+            RailKind::Junction => &sprites.rail_junction,
+            RailKind::Cross => &sprites.rail_cross,
+        }
+    }
+
+    /// Draw a translucent preview of the rail to be placed, tinted red if it cannot be placed.
+    pub fn draw_placement(
+        &mut self,
+        model: &Model,
+        position: vec2<ICoord>,
+        orientation: RailOrientation,
+        framebuffer: &mut ugli::Framebuffer,
+    ) {
+        let color = if model.can_place_rail(position, orientation.kind) {
+            Color::WHITE
+        } else {
+            Color::try_from("#ff4040").unwrap()
+        };
+        let color = crate::util::with_alpha(color, 0.5);
+
+        let texture = self.rail_texture(orientation.kind);
+        let draw = geng_utils::texture::DrawTexture::new(texture).pixel_perfect(
+            model.grid.grid_to_world(position).as_f32(),
+            vec2(0.5, 0.5),
+            &model.camera,
+            framebuffer,
+        );
+        self.context.geng.draw2d().draw2d(
+            framebuffer,
+            &model.camera,
+            &draw2d::TexturedQuad::unit_colored(draw.texture, color).transform(
+                mat3::translate(draw.target.center())
+                    * mat3::rotate(Angle::from_degrees(90.0) * (orientation.rotation as f32 - 1.0))
+                    * mat3::scale(draw.target.size() / 2.0),
+            ),
+        );
+    }
+
     pub fn draw_game(
         &mut self,
         model: &Model,
@@ -81,29 +131,38 @@ impl GameRender {
         // Rails
         for (&pos, rail) in query!(model.grid_items, (&position, &rail.Get.Some)) {
             let position = model.grid.grid_to_world(pos);
-            let texture = match rail.orientation.kind {
-                RailKind::Straight => &self.context.assets.sprites.rail_straight,
-                RailKind::Left => &self.context.assets.sprites.rail_left,
-            };
             self.util.draw_texture_pp(
-                texture,
+                self.rail_texture(rail.orientation.kind),
                 position.as_f32(),
                 vec2(0.5, 0.5),
                 Angle::from_degrees(90.0) * (rail.orientation.rotation as f32 - 1.0),
                 &model.camera,
                 framebuffer,
             );
+
+            if let Some(side) = rail.branch_side() {
+                // Switch state
+                let color = if rail.switched {
+                    Color::try_from("#7bd84f").unwrap()
+                } else {
+                    Color::try_from("#d84f4f").unwrap()
+                };
+                let offset = Angle::from_degrees(90.0 * side as f32).unit_vec()
+                    * model.grid.cell_size.as_f32()
+                    * 0.3;
+                self.context.geng.draw2d().draw2d(
+                    framebuffer,
+                    &model.camera,
+                    &draw2d::Ellipse::circle(position.as_f32() + offset, 0.08, color),
+                );
+            }
         }
 
         // Resources
         for (&pos, resource) in query!(model.grid_items, (&position, &resource.Get.Some)) {
             let position = model.grid.grid_to_world(pos);
-            let texture = match resource {
-                Resource::Coal => &self.context.assets.sprites.coal,
-                Resource::Coin => &self.context.assets.sprites.coin,
-                Resource::Diamond => &self.context.assets.sprites.diamond,
-                Resource::PlusCent => &self.context.assets.sprites.plus_cent,
-                Resource::GhostFuel => &self.context.assets.sprites.ghost_fuel,
+            let Some(texture) = self.context.assets.resources.get(resource) else {
+                continue;
             };
             self.util.draw_texture_pp(
                 texture,
@@ -115,27 +174,57 @@ impl GameRender {
             );
         }
 
+        // Predicted trajectory
+        if let Phase::Setup = model.phase {
+            let points =
+                model.predict_trajectory(r32(PREDICTION_TIME), r32(1.0 / crate::FIXED_FPS as f32));
+            let color = Color::try_from("#ffda45").unwrap();
+            let chain: Vec<_> = points
+                .iter()
+                .map(|pos| draw2d::ColoredVertex {
+                    a_pos: pos.as_f32(),
+                    a_color: color,
+                })
+                .collect();
+            self.util.draw_dashed_chain(
+                &chain,
+                &DashRenderOptions {
+                    width: 0.08,
+                    dash_length: 0.3,
+                    space_length: 0.2,
+                },
+                &model.camera,
+                framebuffer,
+            );
+        }
+
         // Train
+        let alpha = if model.train.ghost.is_some() {
+            0.5
+        } else {
+            1.0
+        };
         for block in &model.train.blocks {
-            let size = match block.collider.shape {
-                Shape::Circle { .. } => todo!(),
-                Shape::Rectangle { width, height } => {
-                    Aabb2::ZERO.extend_symmetric(vec2(width, height).as_f32() / 2.0)
-                }
-            };
+            let size = shape_bounds(&block.collider.shape);
             self.context.geng.draw2d().draw2d(
                 framebuffer,
                 &model.camera,
-                &draw2d::Quad::new(size, Color::try_from("#ffda45").unwrap())
-                    .rotate(block.collider.rotation.map(R32::as_f32))
-                    .translate(block.collider.position.as_f32()),
+                &draw2d::Quad::new(
+                    size,
+                    crate::util::with_alpha(Color::try_from("#ffda45").unwrap(), alpha),
+                )
+                .rotate(block.collider.rotation.map(R32::as_f32))
+                .translate(block.collider.position.as_f32()),
             );
             self.context.geng.draw2d().draw2d(
                 framebuffer,
                 &model.camera,
-                &draw2d::Quad::new(size, Color::try_from("#ff8142").unwrap())
-                    .rotate(block.collider.rotation.map(R32::as_f32))
-                    .translate(block.collider.position.as_f32() + vec2(-0.1, 0.1)),
+                &draw2d::Quad::new(
+                    size,
+                    crate::util::with_alpha(Color::try_from("#ff8142").unwrap(), alpha),
+                )
+                .rotate(block.collider.rotation.map(R32::as_f32))
+                .translate(block.collider.position.as_f32() + vec2(-0.1, 0.1)),
             );
 
             // let draw =
@@ -161,36 +250,45 @@ impl GameRender {
             }
         }
 
+        // Debris
+        for debris in &model.debris {
+            let size = shape_bounds(&debris.collider.shape);
+            self.context.geng.draw2d().draw2d(
+                framebuffer,
+                &model.camera,
+                &draw2d::Quad::new(size, Color::try_from("#7a6a55").unwrap())
+                    .rotate(debris.collider.rotation.map(R32::as_f32))
+                    .translate(debris.collider.position.as_f32()),
+            );
+        }
+
         // Particles
         #[derive(ugli::Vertex)]
         struct ParticleInstance {
             pub i_color: Rgba<f32>,
             pub i_model_matrix: mat3<f32>,
         }
-        let instances: Vec<_> = query!(model.particles, (&kind, &position, &radius, &lifetime))
-            .map(|(kind, position, radius, lifetime)| {
-                let color = match kind {
-                    ParticleKind::Steam => Color::try_from("#3d3957aa").unwrap(),
-                    ParticleKind::Wall => Color::try_from("#ab1f65").unwrap(),
-                    ParticleKind::WagonDestroyed => Color::try_from("#ffda45").unwrap(),
-                    ParticleKind::Collect(resource) => match resource {
-                        Resource::Coal => Color::try_from("#ff8142").unwrap(),
-                        Resource::Coin => Color::try_from("#ffda45").unwrap(),
-                        Resource::Diamond => Color::try_from("#49e7ec").unwrap(),
-                        Resource::PlusCent => Color::try_from("#ffda45").unwrap(),
-                        Resource::GhostFuel => Color::try_from("#ff8142").unwrap(),
-                    },
-                };
-                let t = lifetime.get_ratio().as_f32().sqrt();
-                let color = crate::util::with_alpha(color, t);
-                let transform =
-                    mat3::translate(position.as_f32()) * mat3::scale_uniform(radius.as_f32() * t);
-                ParticleInstance {
-                    i_color: color,
-                    i_model_matrix: transform,
-                }
-            })
-            .collect();
+        let instances: Vec<_> = query!(
+            self.effects.particles,
+            (&kind, &position, &radius, &lifetime)
+        )
+        .map(|(kind, position, radius, lifetime)| {
+            let color = match kind {
+                ParticleKind::Steam => Color::try_from("#3d3957aa").unwrap(),
+                ParticleKind::Wall => Color::try_from("#ab1f65").unwrap(),
+                ParticleKind::WagonDestroyed => Color::try_from("#ffda45").unwrap(),
+                ParticleKind::Collect(color) => *color,
+            };
+            let t = lifetime.get_ratio().as_f32().sqrt();
+            let color = crate::util::with_alpha(color, t);
+            let transform =
+                mat3::translate(position.as_f32()) * mat3::scale_uniform(radius.as_f32() * t);
+            ParticleInstance {
+                i_color: color,
+                i_model_matrix: transform,
+            }
+        })
+        .collect();
         let instances = ugli::VertexBuffer::new_dynamic(self.context.geng.ugli(), instances);
         ugli::draw(
             framebuffer,
@@ -206,7 +304,7 @@ impl GameRender {
 
         // Text
         for (text, position, size, color, lifetime) in query!(
-            model.floating_texts,
+            self.effects.floating_texts,
             (&text, &position, &size, &color, &lifetime)
         ) {
             let t = lifetime.get_ratio().as_f32().sqrt();
@@ -220,3 +318,13 @@ impl GameRender {
         }
     }
 }
+
+/// Bounding box of the shape centered at the origin.
+fn shape_bounds(shape: &Shape) -> Aabb2<f32> {
+    match *shape {
+        Shape::Circle { radius } => Aabb2::ZERO.extend_uniform(radius.as_f32()),
+        Shape::Rectangle { width, height } => {
+            Aabb2::ZERO.extend_symmetric(vec2(width, height).as_f32() / 2.0)
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Headless runs of the game model.
+
+use geng::prelude::*;
+use geng_utils::bounded::Bounded;
+use trake::model::*;
+
+const SEED: u64 = 7;
+const DAYS: usize = 3;
+const MAX_TICKS: usize = 20_000;
+
+fn config() -> Config {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/config.toml");
+    let config = std::fs::read_to_string(path).expect("when reading the config");
+    let config: Config = toml::from_str(&config).expect("when parsing the config");
+    config.validate().expect("when validating the config");
+    config
+}
+
+fn delta_time() -> FloatTime {
+    FloatTime::new(1.0 / trake::FIXED_FPS as f32)
+}
+
+/// Play the model with scripted inputs for `days` days, recording everything into the replay.
+fn play(model: &mut Model, replay: &mut Replay, days: usize) {
+    for tick in 0..MAX_TICKS {
+        match model.phase {
+            Phase::Setup => {
+                if model.days_survived >= days {
+                    break;
+                }
+                let mut actions = vec![PlayerAction::BuyShop(0)];
+                if let Some(&kind) = model.hand.first() {
+                    actions.push(PlayerAction::PlaceRail {
+                        position: vec2(3, 3),
+                        orientation: RailOrientation { kind, rotation: 0 },
+                    });
+                }
+                actions.push(PlayerAction::LaunchTrain);
+                for action in actions {
+                    replay.record_action(action.clone());
+                    model.apply_action(action);
+                }
+            }
+            Phase::Resolution => {
+                let turn = if tick % 90 < 20 { 1.0 } else { 0.0 };
+                let input = PlayerInput {
+                    turn: Coord::new(turn),
+                };
+                replay.record_tick(&input);
+                model.update(delta_time(), input);
+            }
+            Phase::GameOver | Phase::LevelComplete => break,
+        }
+        model.events.clear();
+    }
+}
+
+#[test]
+fn save_round_trip() {
+    let config = config();
+    let mut model = Model::new(config.clone(), SEED);
+    play(&mut model, &mut Replay::new(SEED, &config, None), 1);
+
+    let save = model
+        .save()
+        .expect("the model should be in the setup phase");
+    let data = ron::to_string(&save).unwrap();
+    let save: RunSave = ron::from_str(&data).unwrap();
+    let loaded = Model::load(config, save.clone());
+
+    assert_eq!(loaded.total_score, model.total_score);
+    assert_eq!(loaded.money, model.money);
+    assert_eq!(loaded.days_survived, model.days_survived);
+    assert_eq!(loaded.quotas_completed, model.quotas_completed);
+    assert_eq!(loaded.hand, model.hand);
+    assert_eq!(loaded.modifiers, model.modifiers);
+
+    // The loaded model reseeds its rng, so only the seed differs between the saves
+    let mut resaved = loaded
+        .save()
+        .expect("the loaded model should be in the setup phase");
+    resaved.rng_seed = save.rng_seed;
+    assert_eq!(ron::to_string(&resaved).unwrap(), data);
+}
+
+#[test]
+fn same_seed_same_run() {
+    let config = config();
+    let run = || {
+        let mut model = Model::new(config.clone(), SEED);
+        play(&mut model, &mut Replay::new(SEED, &config, None), DAYS);
+        model
+    };
+    let a = run();
+    let b = run();
+
+    assert_eq!(a.total_score, b.total_score);
+    assert_eq!(a.money, b.money);
+    assert_eq!(a.days_survived, b.days_survived);
+}
+
+#[test]
+fn replay_reproduces_run() {
+    let config = config();
+    let mut model = Model::new(config.clone(), SEED);
+    let mut replay = Replay::new(SEED, &config, None);
+    play(&mut model, &mut replay, DAYS);
+
+    let data = ron::to_string(&replay).unwrap();
+    let replay: Replay = ron::from_str(&data).unwrap();
+    let replayed = replay.simulate(config, delta_time());
+
+    assert_eq!(replayed.total_score, model.total_score);
+    assert_eq!(replayed.money, model.money);
+    assert_eq!(replayed.days_survived, model.days_survived);
+    assert_eq!(replayed.quotas_completed, model.quotas_completed);
+}
+
+#[test]
+fn config_hash_ignores_resource_order() {
+    // Every map gets its own random iteration order
+    assert_eq!(config_hash(&config()), config_hash(&config()));
+}
+
+fn rail_speed(op: ModifierOp, cap: Option<f32>) -> Modifier {
+    Modifier {
+        stat: TrainStat::RailSpeed,
+        op,
+        cap: cap.map(r32),
+    }
+}
+
+/// Rail speed after applying the modifiers to a base of 10.
+fn modified_rail_speed(modifiers: &[Modifier]) -> f32 {
+    let mut train = config().train;
+    train.rail_speed = r32(10.0);
+    train.with_modifiers(modifiers).rail_speed.as_f32()
+}
+
+#[test]
+fn modifiers_add_before_multiplying() {
+    let modifiers = [
+        rail_speed(ModifierOp::Multiply(r32(2.0)), None),
+        rail_speed(ModifierOp::Add(r32(1.0)), None),
+    ];
+    assert_eq!(modified_rail_speed(&modifiers), 22.0);
+}
+
+#[test]
+fn modifier_caps_bound_the_final_value() {
+    let increase = [rail_speed(ModifierOp::Add(r32(5.0)), Some(12.0))];
+    assert_eq!(modified_rail_speed(&increase), 12.0);
+
+    let decrease = [rail_speed(ModifierOp::Multiply(r32(0.5)), Some(8.0))];
+    assert_eq!(modified_rail_speed(&decrease), 8.0);
+
+    // The cap applies to the stat after the later multiplier too
+    let stacked = [
+        rail_speed(ModifierOp::Add(r32(1.0)), Some(12.0)),
+        rail_speed(ModifierOp::Multiply(r32(2.0)), None),
+    ];
+    assert_eq!(modified_rail_speed(&stacked), 12.0);
+}
+
+#[test]
+fn modifier_caps_keep_the_base_value() {
+    let increase = [rail_speed(ModifierOp::Add(r32(1.0)), Some(5.0))];
+    assert_eq!(modified_rail_speed(&increase), 10.0);
+
+    let decrease = [rail_speed(ModifierOp::Multiply(r32(0.5)), Some(15.0))];
+    assert_eq!(modified_rail_speed(&decrease), 10.0);
+}
+
+#[test]
+fn config_rejects_undefined_resources() {
+    let mut config = config();
+    config.deck.resources.push("Undefined".to_owned());
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn level_keeps_the_depo_clear() {
+    let config = config();
+    let mut level = Level::empty(vec2(9, 7));
+    level.depo.exit = vec2(5, 4);
+    level.walls.push(vec2(2, 4));
+    level.walls.push(vec2(6, 5));
+    level.validate(&config).unwrap();
+
+    let mut walled_off = level.clone();
+    walled_off.walls.push(vec2(5, 4));
+    assert!(walled_off.validate(&config).is_err());
+
+    let mut covered = level;
+    covered.walls.push(vec2(4, 4));
+    assert!(covered.validate(&config).is_err());
+}
+
+/// Model on the level with the train already launched.
+fn launch(level: Level) -> Model {
+    let mut model = Model::with_level(config(), SEED, Some(level));
+    model.launch_train();
+    model
+}
+
+#[test]
+fn ghost_stays_inside_the_map() {
+    let mut level = Level::empty(vec2(15, 15));
+    level.walls.extend((1..=15).map(|y| vec2(8, y)));
+    let mut model = launch(level);
+    model.train.ghost = Some(Bounded::new_max(r32(100.0)));
+
+    let mut passed_wall = false;
+    for _ in 0..600 {
+        model.update(delta_time(), PlayerInput::default());
+        model.events.clear();
+        let Phase::Resolution = model.phase else {
+            break;
+        };
+        if model.train.in_depo {
+            continue;
+        }
+        let head = model.train.blocks.front().unwrap().collider.position;
+        assert!(
+            model.is_inside_map(model.grid.world_to_grid(head)),
+            "the ghost left the map at {:?}",
+            head
+        );
+        passed_wall |= head.x > r32(8.0);
+    }
+    assert!(passed_wall, "the ghost did not pass the interior wall");
+}
+
+/// Replace the launched train with a straight one heading right, locomotive first.
+fn set_train(model: &mut Model, head: vec2<f32>, blocks: usize) {
+    model.train.in_depo = false;
+    model.train.blocks = (0..blocks)
+        .map(|i| {
+            let position = vec2(head.x - i as f32, head.y).map(r32);
+            if i == 0 {
+                TrainBlock::new_locomotive(&model.stats, position)
+            } else {
+                TrainBlock::new_wagon(&model.stats, position)
+            }
+        })
+        .collect();
+}
+
+#[test]
+fn head_on_bounce_keeps_the_wagons() {
+    let mut model = launch(Level::empty(vec2(15, 15)));
+    set_train(&mut model, vec2(8.0, 8.0), 5);
+
+    let mut detached = None;
+    for _ in 0..240 {
+        model.update(delta_time(), PlayerInput::default());
+        for event in std::mem::take(&mut model.events) {
+            match event {
+                GameEvent::Derailed { detached: n, .. } => {
+                    detached = Some(detached.unwrap_or(0) + n)
+                }
+                GameEvent::WagonDestroyed { .. } => {
+                    panic!("the locomotive ran into its wagons after the bounce")
+                }
+                _ => {}
+            }
+        }
+        let Phase::Resolution = model.phase else {
+            break;
+        };
+        if let Some(detached) = detached {
+            assert_eq!(model.train.blocks.len(), 5 - detached);
+        }
+    }
+    assert!(detached.is_some(), "the train never hit the wall");
+}
+
+/// Ten times the rail speed covers more than a cell every tick.
+const FAST: f32 = 10.0;
+
+#[test]
+fn fast_head_stops_at_walls() {
+    let mut level = Level::empty(vec2(15, 15));
+    level.walls.push(vec2(10, 8));
+    let mut model = launch(level);
+    set_train(&mut model, vec2(5.0, 8.0), 1);
+    model.train.train_speed = model.stats.rail_speed * r32(FAST);
+
+    let mut hit = false;
+    for _ in 0..30 {
+        model.update(delta_time(), PlayerInput::default());
+        hit |= model
+            .events
+            .drain(..)
+            .any(|event| matches!(event, GameEvent::Derailed { .. }));
+        let Phase::Resolution = model.phase else {
+            break;
+        };
+        let head = model.train.blocks.front().unwrap().collider.position;
+        // The wall spans 9.55 to 10.45
+        assert!(head.x < r32(9.55), "the head passed the wall at {:?}", head);
+    }
+    assert!(hit, "the head never hit the wall");
+}
+
+#[test]
+fn fast_head_visits_every_rail() {
+    let mut level = Level::empty(vec2(15, 15));
+    let rails: Vec<vec2<ICoord>> = (3..=12).map(|x| vec2(x, 8)).collect();
+    for &position in &rails {
+        let orientation = RailOrientation {
+            kind: RailKind::Straight,
+            rotation: 0,
+        };
+        level.set_rail(position, orientation);
+    }
+    let mut model = launch(level);
+    set_train(&mut model, vec2(2.0, 8.0), 1);
+    model.train.train_speed = model.stats.rail_speed * r32(FAST);
+
+    let mut visited = HashSet::new();
+    for _ in 0..30 {
+        model.update(delta_time(), PlayerInput::default());
+        model.events.clear();
+        let Phase::Resolution = model.phase else {
+            break;
+        };
+        let head = model.train.blocks.front().unwrap();
+        visited.extend(head.path.iter().map(|&pos| model.grid.world_to_grid(pos)));
+        if head.collider.position.x > r32(13.0) {
+            break;
+        }
+    }
+    for position in rails {
+        assert!(
+            visited.contains(&position),
+            "skipped the rail at {:?}",
+            position
+        );
+    }
+}